@@ -0,0 +1,176 @@
+use crate::{AssetSource, Loader, LoaderError};
+use std::sync::Arc;
+use tokio::task::spawn_blocking;
+
+/// Asset source that can be queried without blocking the async runtime.
+///
+/// Implemented for the [`Loader`] and for any shared [`AssetSource`], the blocking reads are done on the blocking
+/// thread pool of the current tokio runtime. Requires the `tokio` feature.
+pub trait AsyncAssetSource: Send + Sync {
+    /// Check if a file by path exists
+    fn has_async(
+        &self,
+        path: &str,
+    ) -> impl Future<Output = Result<bool, LoaderError>> + Send + 'static;
+
+    /// Load a file by path, returns `None` if the path doesn't exist
+    fn load_async(
+        &self,
+        path: &str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, LoaderError>> + Send + 'static;
+}
+
+impl<S: AssetSource + Send + Sync + ?Sized + 'static> AsyncAssetSource for Arc<S> {
+    fn has_async(
+        &self,
+        path: &str,
+    ) -> impl Future<Output = Result<bool, LoaderError>> + Send + 'static {
+        spawn_source(self, path, |source, path| source.has(path))
+    }
+
+    fn load_async(
+        &self,
+        path: &str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, LoaderError>> + Send + 'static {
+        spawn_source(self, path, |source, path| source.load(path))
+    }
+}
+
+impl AsyncAssetSource for Loader {
+    fn has_async(
+        &self,
+        path: &str,
+    ) -> impl Future<Output = Result<bool, LoaderError>> + Send + 'static {
+        self.exists_async(path)
+    }
+
+    fn load_async(
+        &self,
+        path: &str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, LoaderError>> + Send + 'static {
+        Loader::load_async(self, path)
+    }
+}
+
+fn spawn_source<S, T, F>(
+    source: &Arc<S>,
+    path: &str,
+    f: F,
+) -> impl Future<Output = Result<T, LoaderError>> + Send + 'static
+where
+    S: AssetSource + Send + Sync + ?Sized + 'static,
+    T: Send + 'static,
+    F: FnOnce(&S, &str) -> Result<T, LoaderError> + Send + 'static,
+{
+    let source = source.clone();
+    let path = path.to_string();
+    async move {
+        spawn_blocking(move || f(&source, &path))
+            .await
+            .map_err(|e| LoaderError::Other(format!("blocking load task failed: {e}")))?
+    }
+}
+
+impl Loader {
+    /// Load a file by path without blocking the async runtime.
+    ///
+    /// The loading is done by [`load`](Self::load) on the blocking thread pool of the current tokio runtime,
+    /// so this has to be awaited from within a tokio runtime. Requires the `tokio` feature.
+    ///
+    /// Returns the file data as `Vec<u8>` or `None` if the path doesn't exist.
+    pub fn load_async(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, LoaderError>> + Send + 'static {
+        self.spawn(name, |loader, name| loader.load(name))
+    }
+
+    /// Check if a file by path exists without blocking the async runtime.
+    ///
+    /// Like [`load_async`](Self::load_async) the check is done by [`exists`](Self::exists) on the blocking thread
+    /// pool of the current tokio runtime. Requires the `tokio` feature.
+    pub fn exists_async(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<bool, LoaderError>> + Send + 'static {
+        self.spawn(name, |loader, name| loader.exists(name))
+    }
+
+    fn spawn<T, F>(
+        &self,
+        name: &str,
+        f: F,
+    ) -> impl Future<Output = Result<T, LoaderError>> + Send + 'static
+    where
+        T: Send + 'static,
+        F: FnOnce(&Loader, &str) -> Result<T, LoaderError> + Send + 'static,
+    {
+        let loader = self.clone();
+        let name = name.to_string();
+        async move {
+            spawn_blocking(move || f(&loader, &name))
+                .await
+                .map_err(|e| LoaderError::Other(format!("blocking load task failed: {e}")))?
+        }
+    }
+}
+
+#[test]
+fn test_load_async() {
+    use crate::source::MemorySource;
+    use tokio::runtime::Builder;
+
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([("scripts/items.txt", "items")]));
+
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let data = runtime.block_on(loader.load_async("scripts/items.txt"));
+    assert_eq!(Some(b"items".to_vec()), data.unwrap());
+    let missing = runtime.block_on(loader.load_async("scripts/missing.txt"));
+    assert!(missing.unwrap().is_none());
+    assert!(
+        runtime
+            .block_on(loader.exists_async("scripts/items.txt"))
+            .unwrap()
+    );
+    assert!(
+        !runtime
+            .block_on(loader.exists_async("scripts/missing.txt"))
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_async_asset_source() {
+    use crate::source::MemorySource;
+    use tokio::runtime::Builder;
+
+    let source: Arc<dyn AssetSource + Send + Sync> =
+        Arc::new(MemorySource::from_iter([("scripts/items.txt", "items")]));
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([("scripts/items.txt", "items")]));
+
+    let runtime = Builder::new_current_thread().build().unwrap();
+    assert_eq!(
+        Some(b"items".to_vec()),
+        runtime
+            .block_on(source.load_async("scripts/items.txt"))
+            .unwrap()
+    );
+    assert!(
+        !runtime
+            .block_on(source.has_async("scripts/missing.txt"))
+            .unwrap()
+    );
+    assert!(
+        runtime
+            .block_on(AsyncAssetSource::has_async(&loader, "scripts/items.txt"))
+            .unwrap()
+    );
+    assert_eq!(
+        None,
+        runtime
+            .block_on(AsyncAssetSource::load_async(&loader, "scripts/missing.txt"))
+            .unwrap()
+    );
+}
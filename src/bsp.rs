@@ -0,0 +1,533 @@
+use crate::source::AssetSource;
+use crate::{Loader, LoaderError, SourceHandle};
+use lzma_rs::decompress::{Options, UnpackedSize};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::result::ZipError;
+use zip::{CompressionMethod, ZipArchive};
+
+const LUMP_COUNT: usize = 64;
+const PAKFILE_LUMP: usize = 40;
+/// Size of the bsp header containing the lump directory
+const HEADER_SIZE: usize = 8 + LUMP_COUNT * 16;
+
+/// Names of the lumps by index, matching the engine's `LUMP_*` constants
+const LUMP_NAMES: [&str; LUMP_COUNT] = [
+    "entities",
+    "planes",
+    "texdata",
+    "vertexes",
+    "visibility",
+    "nodes",
+    "texinfo",
+    "faces",
+    "lighting",
+    "occlusion",
+    "leafs",
+    "faceids",
+    "edges",
+    "surfedges",
+    "models",
+    "worldlights",
+    "leaffaces",
+    "leafbrushes",
+    "brushes",
+    "brushsides",
+    "areas",
+    "areaportals",
+    "unused0",
+    "unused1",
+    "unused2",
+    "unused3",
+    "dispinfo",
+    "originalfaces",
+    "physdisp",
+    "physcollide",
+    "vertnormals",
+    "vertnormalindices",
+    "disp_lightmap_alphas",
+    "disp_verts",
+    "disp_lightmap_sample_positions",
+    "game_lump",
+    "leafwaterdata",
+    "primitives",
+    "primverts",
+    "primindices",
+    "pakfile",
+    "clipportalverts",
+    "cubemaps",
+    "texdata_string_data",
+    "texdata_string_table",
+    "overlays",
+    "leafmindisttowater",
+    "face_macro_texture_info",
+    "disp_tris",
+    "physcollidesurface",
+    "wateroverlays",
+    "leaf_ambient_index_hdr",
+    "leaf_ambient_index",
+    "lighting_hdr",
+    "worldlights_hdr",
+    "leaf_ambient_lighting_hdr",
+    "leaf_ambient_lighting",
+    "xzippakfile",
+    "faces_hdr",
+    "map_flags",
+    "overlay_fades",
+    "overlay_system_levels",
+    "physlevel",
+    "disp_multiblend",
+];
+
+/// Location of a lump inside a bsp file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LumpEntry {
+    pub offset: u64,
+    pub length: u64,
+    /// uncompressed size of the lump, or 0 if the lump isn't compressed
+    pub uncompressed_length: u32,
+}
+
+/// Read the lump directory from the header of a bsp file
+pub(crate) fn read_lump_entries<R: Read>(reader: &mut R) -> Result<Vec<LumpEntry>, LoaderError> {
+    let mut header = [0; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"VBSP" {
+        return Err(LoaderError::Other("file is not a valid bsp".into()));
+    }
+    Ok(header[8..]
+        .chunks_exact(16)
+        .map(|lump| LumpEntry {
+            offset: u32::from_le_bytes(lump[0..4].try_into().unwrap()) as u64,
+            length: u32::from_le_bytes(lump[4..8].try_into().unwrap()) as u64,
+            uncompressed_length: u32::from_le_bytes(lump[12..16].try_into().unwrap()),
+        })
+        .collect())
+}
+
+fn lump_index(lump: &str) -> Option<usize> {
+    LUMP_NAMES
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(lump))
+        .or_else(|| lump.parse().ok().filter(|index| *index < LUMP_COUNT))
+}
+
+/// Decompress a lump stored with the engine's lzma header
+fn decompress_lump(data: &[u8]) -> Result<Vec<u8>, LoaderError> {
+    let invalid = || LoaderError::Other("invalid compressed bsp lump".into());
+    if data.len() < 17 || &data[0..4] != b"LZMA" {
+        return Err(invalid());
+    }
+    let actual_size = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    let mut output = Vec::with_capacity(actual_size as usize);
+    lzma_rs::lzma_decompress_with_options(
+        &mut Cursor::new(&data[12..]),
+        &mut output,
+        &Options {
+            unpacked_size: UnpackedSize::UseProvided(Some(actual_size as u64)),
+            allow_incomplete: false,
+            memlimit: None,
+        },
+    )
+    .map_err(|_| invalid())?;
+    Ok(output)
+}
+
+impl Loader {
+    /// Load the raw data of a lump from a bsp file loaded through the loader.
+    ///
+    /// Lumps are named after the engine's `LUMP_*` constants in lowercase without the prefix,
+    /// e.g. `entities`, `texdata_string_data`, `game_lump` or `pakfile`, the lump index can also be used.
+    /// Compressed lumps are decompressed, but the individual sub-lumps of the game lump are returned as stored.
+    ///
+    /// Only the header and the lump itself are read from the bsp file. Returns `None` if the bsp file doesn't exist.
+    pub fn load_bsp_lump(
+        &self,
+        bsp_path: &str,
+        lump: &str,
+    ) -> Result<Option<Vec<u8>>, LoaderError> {
+        let index = lump_index(lump)
+            .ok_or_else(|| LoaderError::Other(format!("unknown bsp lump {lump}")))?;
+        let Some(header) = self.load_range(bsp_path, 0..HEADER_SIZE as u64)? else {
+            return Ok(None);
+        };
+        let entry = read_lump_entries(&mut header.as_slice())?[index];
+        let data = self
+            .load_range(bsp_path, entry.offset..entry.offset + entry.length)?
+            .filter(|data| data.len() as u64 == entry.length)
+            .ok_or_else(|| LoaderError::Other(format!("bsp lump {lump} is out of bounds")))?;
+        if entry.uncompressed_length == 0 {
+            Ok(Some(data))
+        } else {
+            decompress_lump(&data).map(Some)
+        }
+    }
+
+    /// Mount the packfile embedded in the bsp file at the specified path, searched before all existing sources.
+    ///
+    /// The packfile is read into memory, use [`add_bsp_lazy`](Self::add_bsp_lazy) to read the embedded files
+    /// from disk on demand instead.
+    pub fn mount_bsp<P: AsRef<Path>>(&mut self, bsp_path: P) -> Result<SourceHandle, LoaderError> {
+        let bsp = std::fs::read(bsp_path)?;
+        self.mount_bsp_data(&bsp)
+    }
+
+    /// Mount the packfile embedded in already loaded bsp data, searched before all existing sources.
+    ///
+    /// The packfile is mounted as a zip archive, so the embedded files can be [listed](Self::list) without
+    /// copying the packfile.
+    pub fn mount_bsp_data(&mut self, bsp: &[u8]) -> Result<SourceHandle, LoaderError> {
+        let entry = read_lump_entries(&mut &bsp[..])?[PAKFILE_LUMP];
+        let pakfile = lump_data(bsp, entry, "pakfile")?.to_vec();
+        let zip = ZipArchive::new(Cursor::new(pakfile))?;
+        Ok(self.insert_source_front(Mutex::new(zip)))
+    }
+
+    /// Load a map through the loader and mount its packfile, searched before all existing sources.
+    ///
+    /// The map is looked up like any other asset, like `maps/pl_upward.bsp`, so maps inside vpk files or
+    /// [workshop maps](crate::LoaderBuilder::mount_workshop) can be mounted as well.
+    /// Returns `None` if the map doesn't exist.
+    pub fn mount_map(&mut self, name: &str) -> Result<Option<SourceHandle>, LoaderError> {
+        match self.load(name)? {
+            Some(bsp) => self.mount_bsp_data(&bsp).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn lump_data<'a>(bsp: &'a [u8], entry: LumpEntry, lump: &str) -> Result<&'a [u8], LoaderError> {
+    usize::try_from(entry.offset + entry.length)
+        .ok()
+        .and_then(|end| bsp.get(entry.offset as usize..end))
+        .ok_or_else(|| LoaderError::Other(format!("bsp lump {lump} is out of bounds")))
+}
+
+/// A reader over a section of a file
+struct FileSlice {
+    file: File,
+    start: u64,
+    length: u64,
+    position: u64,
+}
+
+impl Read for FileSlice {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.position);
+        let max = buf.len().min(remaining as usize);
+        let read = self.file.read(&mut buf[..max])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for FileSlice {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| std::io::Error::other("seek before the start of the slice"))?;
+        self.file.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// The packfile embedded in a bsp, read from disk on demand.
+///
+/// Only the directory of the packfile is kept in memory, the file contents are read from the bsp when loaded.
+pub struct LazyPackfile {
+    zip: Mutex<ZipArchive<FileSlice>>,
+    bsp_path: PathBuf,
+    /// offset of the packfile in the bsp
+    offset: u64,
+}
+
+impl LazyPackfile {
+    /// Open the packfile from the bsp at the specified path
+    pub fn open<P: AsRef<Path>>(bsp_path: P) -> Result<Self, LoaderError> {
+        let bsp_path = bsp_path.as_ref().to_path_buf();
+        let mut file = File::open(&bsp_path)?;
+        let pakfile = read_lump_entries(&mut file)?[PAKFILE_LUMP];
+        let mut slice = FileSlice {
+            file,
+            start: pakfile.offset,
+            length: pakfile.length,
+            position: 0,
+        };
+        slice.seek(SeekFrom::Start(0))?;
+        Ok(LazyPackfile {
+            zip: Mutex::new(ZipArchive::new(slice)?),
+            bsp_path,
+            offset: pakfile.offset,
+        })
+    }
+}
+
+impl AssetSource for LazyPackfile {
+    fn has(&self, path: &str) -> Result<bool, LoaderError> {
+        self.zip.has(path)
+    }
+
+    fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        self.zip.load(path)
+    }
+
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        self.zip.size(path)
+    }
+
+    fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        self.zip.stored_crc(path)
+    }
+
+    /// Uncompressed files are read directly from the bsp file, compressed files are decompressed into memory
+    fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        let (start, size) = {
+            let mut zip = self.zip.lock().unwrap();
+            let entry = match zip.by_name(path) {
+                Ok(entry) => entry,
+                Err(ZipError::FileNotFound) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            if entry.compression() != CompressionMethod::Stored {
+                drop(entry);
+                drop(zip);
+                return self.zip.open(path);
+            }
+            (entry.data_start(), entry.size())
+        };
+        let mut file = File::open(&self.bsp_path)?;
+        file.seek(SeekFrom::Start(self.offset + start))?;
+        Ok(Some(Box::new(file.take(size))))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        self.zip.list(prefix)
+    }
+
+    fn describe(&self) -> String {
+        let name = self.bsp_path.file_name().unwrap_or_default();
+        format!("bsp:{}", name.to_string_lossy())
+    }
+
+    fn is_packed(&self) -> bool {
+        true
+    }
+}
+
+/// Build a minimal bsp containing only a packfile with the provided files
+#[cfg(test)]
+pub(crate) fn build_test_bsp(files: &[(&str, &[u8])]) -> Vec<u8> {
+    build_test_bsp_with_lumps(files, &[])
+}
+
+/// Build a minimal bsp containing a packfile and the provided lumps as `(index, data, uncompressed_length)`
+#[cfg(test)]
+fn build_test_bsp_with_lumps(files: &[(&str, &[u8])], lumps: &[(usize, &[u8], u32)]) -> Vec<u8> {
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in files {
+        zip.start_file(
+            *name,
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )
+        .unwrap();
+        zip.write_all(data).unwrap();
+    }
+    let pakfile = zip.finish().unwrap().into_inner();
+
+    let mut lumps = lumps.to_vec();
+    lumps.push((PAKFILE_LUMP, &pakfile, 0));
+
+    let header_length = 8 + LUMP_COUNT * 16 + 4;
+    let mut bsp = Vec::new();
+    bsp.extend_from_slice(b"VBSP");
+    bsp.extend_from_slice(&20u32.to_le_bytes());
+    let mut offset = header_length;
+    for index in 0..LUMP_COUNT {
+        let (length, uncompressed_length) = match lumps.iter().find(|lump| lump.0 == index) {
+            Some((_, data, uncompressed_length)) => (data.len(), *uncompressed_length),
+            None => (0, 0),
+        };
+        bsp.extend_from_slice(&(offset as u32).to_le_bytes());
+        bsp.extend_from_slice(&(length as u32).to_le_bytes());
+        bsp.extend_from_slice(&0u32.to_le_bytes());
+        bsp.extend_from_slice(&uncompressed_length.to_le_bytes());
+        offset += length;
+    }
+    bsp.extend_from_slice(&1u32.to_le_bytes());
+    for index in 0..LUMP_COUNT {
+        if let Some((_, data, _)) = lumps.iter().find(|lump| lump.0 == index) {
+            bsp.extend_from_slice(data);
+        }
+    }
+    bsp
+}
+
+#[test]
+fn test_lazy_packfile() {
+    let dir = tempfile::tempdir().unwrap();
+    let bsp_path = dir.path().join("test.bsp");
+    std::fs::write(
+        &bsp_path,
+        build_test_bsp(&[("materials/test/embedded.vmt", b"embedded")]),
+    )
+    .unwrap();
+
+    let mut loader = crate::Loader::empty();
+    loader.add_source(crate::source::MemorySource::from_iter([(
+        "materials/test/embedded.vmt",
+        "base",
+    )]));
+    let handle = loader.add_bsp_lazy(&bsp_path).unwrap();
+    // the packfile takes precedence over existing sources, like with mount_bsp
+    assert_eq!(
+        Some(b"embedded".to_vec()),
+        loader.load("materials/test/embedded.vmt").unwrap()
+    );
+    assert!(!loader.exists("materials/test/missing.vmt").unwrap());
+    let mut data = Vec::new();
+    let mut reader = loader.open("materials/test/embedded.vmt").unwrap().unwrap();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(b"embedded".as_slice(), data);
+    assert_eq!(
+        Some(b"bed".to_vec()),
+        loader
+            .load_range("materials/test/embedded.vmt", 2..5)
+            .unwrap()
+    );
+
+    assert!(loader.remove_source(handle));
+    assert_eq!(
+        Some(b"base".to_vec()),
+        loader.load("materials/test/embedded.vmt").unwrap()
+    );
+    assert!(!loader.remove_source(handle));
+}
+
+#[test]
+fn test_mount_bsp() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("override.vmt"), "base").unwrap();
+    let bsp_path = dir.path().join("test.bsp");
+    let bsp = build_test_bsp(&[("override.vmt", b"embedded"), ("embedded.vmt", b"embedded")]);
+    std::fs::write(&bsp_path, &bsp).unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    let handle = loader.mount_bsp(&bsp_path).unwrap();
+    // the packfile takes precedence over existing sources
+    assert_eq!(
+        Some(b"embedded".to_vec()),
+        loader.load("override.vmt").unwrap()
+    );
+    assert!(loader.exists("embedded.vmt").unwrap());
+    assert_eq!(
+        vec!["embedded.vmt", "override.vmt"],
+        loader.list_glob("*.vmt").unwrap()
+    );
+    assert!(loader.remove_source(handle));
+    assert_eq!(Some(b"base".to_vec()), loader.load("override.vmt").unwrap());
+
+    loader.mount_bsp_data(&bsp).unwrap();
+    assert_eq!(
+        Some(b"embedded".to_vec()),
+        loader.load("override.vmt").unwrap()
+    );
+    assert!(loader.mount_bsp_data(b"not a bsp").is_err());
+}
+
+#[test]
+fn test_mount_map() {
+    use crate::source::MemorySource;
+
+    let bsp = build_test_bsp(&[("materials/embedded.vmt", b"embedded")]);
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([("maps/test.bsp", bsp)]));
+
+    let handle = loader.mount_map("maps/Test.bsp").unwrap().unwrap();
+    assert_eq!(
+        Some(b"embedded".to_vec()),
+        loader.load("materials/embedded.vmt").unwrap()
+    );
+    assert!(loader.remove_source(handle));
+    assert!(!loader.exists("materials/embedded.vmt").unwrap());
+    assert!(loader.mount_map("maps/missing.bsp").unwrap().is_none());
+}
+
+#[test]
+fn test_load_bsp_lump() {
+    let entities = b"{\n\"classname\" \"worldspawn\"\n}\n";
+    let planes = [1u8; 40];
+
+    // convert from the standard lzma header to the engine's
+    let mut compressed = Vec::new();
+    lzma_rs::lzma_compress(&mut &planes[..], &mut compressed).unwrap();
+    let mut lump = b"LZMA".to_vec();
+    lump.extend_from_slice(&(planes.len() as u32).to_le_bytes());
+    lump.extend_from_slice(&((compressed.len() - 13) as u32).to_le_bytes());
+    lump.extend_from_slice(&compressed[0..5]);
+    lump.extend_from_slice(&compressed[13..]);
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("maps")).unwrap();
+    std::fs::write(
+        dir.path().join("maps/test.bsp"),
+        build_test_bsp_with_lumps(&[], &[(0, entities, 0), (1, &lump, planes.len() as u32)]),
+    )
+    .unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    assert_eq!(
+        Some(entities.to_vec()),
+        loader.load_bsp_lump("maps/test.bsp", "entities").unwrap()
+    );
+    assert_eq!(
+        Some(planes.to_vec()),
+        loader.load_bsp_lump("maps/test.bsp", "planes").unwrap()
+    );
+    assert_eq!(
+        Some(planes.to_vec()),
+        loader.load_bsp_lump("maps/test.bsp", "1").unwrap()
+    );
+    assert_eq!(
+        None,
+        loader.load_bsp_lump("maps/missing.bsp", "planes").unwrap()
+    );
+    assert!(loader.load_bsp_lump("maps/test.bsp", "foo").is_err());
+
+    // the map is never loaded in full
+    struct RangeOnly(std::path::PathBuf);
+    impl AssetSource for RangeOnly {
+        fn has(&self, path: &str) -> Result<bool, LoaderError> {
+            self.0.has(path)
+        }
+
+        fn load(&self, _path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            Err(LoaderError::Other("full load".into()))
+        }
+
+        fn load_range(
+            &self,
+            path: &str,
+            range: std::ops::Range<u64>,
+        ) -> Result<Option<Vec<u8>>, LoaderError> {
+            self.0.load_range(path, range)
+        }
+    }
+    let mut loader = Loader::empty();
+    loader.add_source(RangeOnly(dir.path().to_path_buf()));
+    assert_eq!(
+        Some(planes.to_vec()),
+        loader.load_bsp_lump("maps/test.bsp", "planes").unwrap()
+    );
+}
@@ -0,0 +1,1275 @@
+use crate::gameinfo::search_paths;
+use crate::layout::matches_archive;
+#[cfg(feature = "vpk")]
+use crate::vpk_entry::open_vpk;
+use crate::workshop::WorkshopSource;
+use crate::{AssetSource, GameLayout, Loader, LoaderError, Priority, app_paths};
+use glob::Pattern;
+use std::collections::HashSet;
+use std::fmt::{Debug, Formatter};
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Factory that opens an archive found during discovery as a source
+pub type ArchiveFactory =
+    Arc<dyn Fn(&Path) -> Result<Arc<dyn AssetSource + Send + Sync>, LoaderError> + Send + Sync>;
+
+/// Filter on the labels of the discovered sources
+type SourceFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Builder to customize the construction of a [`Loader`].
+///
+/// By default, the tf2 directory is auto-detected and the `tf`, `hl2` and `tf/download` directories are mounted,
+/// together with all vpk files from the `tf` and `hl2` directories and the custom content from `tf/custom`.
+/// Additional directories and vpk files can be mounted after these, or instead of them with
+/// [`mount_defaults`](Self::mount_defaults).
+#[derive(Clone)]
+pub struct LoaderBuilder {
+    tf2_dir: Option<PathBuf>,
+    extra_tf2_dirs: Vec<PathBuf>,
+    layout: GameLayout,
+    scan_timeout: Option<Duration>,
+    mount_platform: bool,
+    mount_bin: bool,
+    require_readonly: bool,
+    hd_content_dir: Option<PathBuf>,
+    use_gameinfo: bool,
+    strict_archives: bool,
+    mount_defaults: bool,
+    mount_download: bool,
+    mount_custom: bool,
+    mount_hl2: bool,
+    mount_workshop: bool,
+    filter: Option<SourceFilter>,
+    mounts: Vec<ExtraMount>,
+    sources: Vec<(Arc<dyn AssetSource + Send + Sync>, Priority)>,
+    archives: Vec<(String, ArchiveFactory)>,
+}
+
+/// Source explicitly added to the builder
+#[derive(Debug, Clone)]
+enum ExtraMount {
+    Dir(PathBuf),
+    #[cfg(feature = "vpk")]
+    Vpk(PathBuf),
+    ScanArchives(PathBuf),
+}
+
+impl Debug for LoaderBuilder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoaderBuilder")
+            .field("tf2_dir", &self.tf2_dir)
+            .field("extra_tf2_dirs", &self.extra_tf2_dirs)
+            .field("layout", &self.layout)
+            .field("scan_timeout", &self.scan_timeout)
+            .field("mount_platform", &self.mount_platform)
+            .field("mount_bin", &self.mount_bin)
+            .field("require_readonly", &self.require_readonly)
+            .field("hd_content_dir", &self.hd_content_dir)
+            .field("use_gameinfo", &self.use_gameinfo)
+            .field("strict_archives", &self.strict_archives)
+            .field("mount_defaults", &self.mount_defaults)
+            .field("mount_download", &self.mount_download)
+            .field("mount_custom", &self.mount_custom)
+            .field("mount_hl2", &self.mount_hl2)
+            .field("mount_workshop", &self.mount_workshop)
+            .field("filtered", &self.filter.is_some())
+            .field("mounts", &self.mounts)
+            .field(
+                "sources",
+                &self
+                    .sources
+                    .iter()
+                    .map(|(source, priority)| (source.describe(), priority))
+                    .collect::<Vec<_>>(),
+            )
+            .field(
+                "archives",
+                &self.archives.iter().map(|(ext, _)| ext).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Default for LoaderBuilder {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut builder = LoaderBuilder {
+            tf2_dir: None,
+            extra_tf2_dirs: Vec::new(),
+            layout: GameLayout::default(),
+            scan_timeout: None,
+            mount_platform: false,
+            mount_bin: false,
+            require_readonly: false,
+            hd_content_dir: None,
+            use_gameinfo: false,
+            strict_archives: false,
+            mount_defaults: true,
+            mount_download: true,
+            mount_custom: true,
+            mount_hl2: true,
+            mount_workshop: false,
+            filter: None,
+            mounts: Vec::new(),
+            sources: Vec::new(),
+            archives: Vec::new(),
+        };
+        #[cfg(feature = "vpk")]
+        {
+            builder = builder.archive_extension(".vpk", |path| Ok(Arc::new(open_vpk(path)?)));
+        }
+        builder
+    }
+}
+
+impl LoaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the specified tf2 directory instead of auto-detecting it.
+    pub fn tf2_dir<P: AsRef<Path>>(mut self, tf2_dir: P) -> Self {
+        self.tf2_dir = Some(tf2_dir.as_ref().into());
+        self
+    }
+
+    /// Mount the default sources of another tf2 directory after those of the main tf2 directory.
+    ///
+    /// This allows loading from multiple installs, like a modded install falling back to a vanilla one.
+    /// Sources from the different directories share the same labels, like `tf` or `hl2`.
+    pub fn add_tf2_dir<P: AsRef<Path>>(mut self, tf2_dir: P) -> Self {
+        self.extra_tf2_dirs.push(tf2_dir.as_ref().into());
+        self
+    }
+
+    /// Load assets for a different Source game, auto-detecting the install directory of the steam app.
+    ///
+    /// The `game_dir` directory is mounted in place of `tf`, together with `hl2`. For tf2 this is app `440`
+    /// with game directory `tf`. An explicit [`tf2_dir`](Self::tf2_dir) is used as the install directory instead.
+    pub fn app(self, app_id: u32, game_dir: &str) -> Self {
+        self.layout(GameLayout::new(app_id, game_dir))
+    }
+
+    /// Load assets for a Source game with a different directory layout, like [`app`](Self::app).
+    ///
+    /// The game directory of the layout is mounted in place of `tf`, and its base directories in place of `hl2`.
+    /// Only the archives matching the patterns of the layout are opened from these directories.
+    pub fn layout(mut self, layout: GameLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Give up on discovering vpk files after the timeout, mounting only the plain directories.
+    ///
+    /// The timeout covers the archives in the game, `hl2`, `platform` and `custom` directories together, the content
+    /// folders in `custom` are still mounted when it runs out if they were listed in time. The scan runs on a
+    /// background thread which is abandoned, not cancelled, when the timeout runs out. By default, there is no timeout.
+    pub fn scan_timeout(mut self, timeout: Duration) -> Self {
+        self.scan_timeout = Some(timeout);
+        self
+    }
+
+    /// Mount the `platform` directory and its vpk files when present in the game directory.
+    ///
+    /// The `platform` directory contains shared ui and engine content for some Source games, it is mounted with
+    /// the lowest priority. By default, the `platform` directory is not mounted.
+    pub fn mount_platform(mut self, mount_platform: bool) -> Self {
+        self.mount_platform = mount_platform;
+        self
+    }
+
+    /// Mount the `tf/bin` and `bin` directories when present in the game directory.
+    ///
+    /// Files in these directories are loaded relative to the `bin` directory, e.g. `x64/client.dll` or
+    /// `stdshader_dx9.dll`, with `tf/bin` taking precedence. They are mounted after all other sources and their
+    /// archives aren't discovered. By default, neither directory is mounted.
+    pub fn mount_bin(mut self, mount_bin: bool) -> Self {
+        self.mount_bin = mount_bin;
+        self
+    }
+
+    /// Mount a directory with high resolution content, like an HD texture pack, in front of all other sources.
+    ///
+    /// Files in this directory take precedence over the game's own files, so its textures replace the base textures
+    /// with the same path. Building the loader fails if the path isn't a directory.
+    pub fn hd_content_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.hd_content_dir = Some(path.as_ref().into());
+        self
+    }
+
+    /// Mount the search paths declared in `tf/gameinfo.txt`, in the declared order.
+    ///
+    /// Only the search paths used for game content are mounted, `.vpk` paths are opened with the registered
+    /// [archive extensions](Self::archive_extension) and wildcard paths like `tf/custom/*` mount every directory and
+    /// archive they match. When `gameinfo.txt` is missing, the default `tf`, `hl2` and `tf/download` layout is used.
+    pub fn use_gameinfo(mut self, use_gameinfo: bool) -> Self {
+        self.use_gameinfo = use_gameinfo;
+        self
+    }
+
+    /// Fail building the loader when a discovered archive can't be opened.
+    ///
+    /// By default, archives that fail to open, like corrupt vpk files, are skipped with a warning.
+    /// With this enabled, [`LoaderError::Archive`] is returned for the first archive that fails to open instead.
+    pub fn strict_archives(mut self, strict_archives: bool) -> Self {
+        self.strict_archives = strict_archives;
+        self
+    }
+
+    /// Mount the game and `hl2` directories of the tf2 directory and their archives.
+    ///
+    /// With this disabled only the explicitly added sources are mounted, and the tf2 directory isn't auto-detected
+    /// unless the `bin` directories are mounted. Enabled by default.
+    pub fn mount_defaults(mut self, mount_defaults: bool) -> Self {
+        self.mount_defaults = mount_defaults;
+        self
+    }
+
+    /// Mount the `download` directory of the game directory, containing content downloaded from servers.
+    ///
+    /// Enabled by default.
+    pub fn enable_download(mut self, enable_download: bool) -> Self {
+        self.mount_download = enable_download;
+        self
+    }
+
+    /// Mount the content folders and vpk files in the `custom` directory of the game directory, like mods and huds.
+    ///
+    /// The custom content is mounted before the game directory, in alphabetical order, with the vpk files inside
+    /// a content folder mounted after the folder itself. Enabled by default.
+    pub fn mount_custom(mut self, mount_custom: bool) -> Self {
+        self.mount_custom = mount_custom;
+        self
+    }
+
+    /// Mount the `hl2` directory and its archives, containing the content shared between source games.
+    ///
+    /// For a different [`layout`](Self::layout) this mounts the base directories of the layout instead.
+    ///
+    /// Most tf2 assets depend on the `hl2` content, so this should only be disabled for tools that only need the
+    /// tf2 specific content. Enabled by default.
+    pub fn mount_hl2(mut self, mount_hl2: bool) -> Self {
+        self.mount_hl2 = mount_hl2;
+        self
+    }
+
+    /// Mount the maps subscribed to through the steam workshop, as `maps/workshop/<name>.ugc<id>.bsp` like the game.
+    ///
+    /// The workshop content is read from the `steamapps/workshop` directory of the steam library containing the tf2
+    /// directory. Mount the packfile of a workshop map with [`Loader::mount_bsp`] to load its packed assets.
+    /// Disabled by default.
+    pub fn mount_workshop(mut self, mount_workshop: bool) -> Self {
+        self.mount_workshop = mount_workshop;
+        self
+    }
+
+    /// Only mount the discovered sources for which `filter` returns `true`.
+    ///
+    /// The filter is called with the label of every source found in the tf2 directory or added with
+    /// [`add_dir`](Self::add_dir), [`add_vpk`](Self::add_vpk) or [`scan_vpks`](Self::scan_vpks). Sources are labeled
+    /// with their path relative to the tf2 directory, like `tf/tf2_misc_dir.vpk` or `hl2`. The filter is applied
+    /// again when the loader is [refreshed](Loader::refresh).
+    pub fn filter_sources<F: Fn(&str) -> bool + Send + Sync + 'static>(
+        mut self,
+        filter: F,
+    ) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Mount a source after the discovered sources, like [`Loader::add_source`].
+    pub fn add_source<S: AssetSource + Send + Sync + 'static>(self, source: S) -> Self {
+        self.add_source_with_priority(source, Priority::Base)
+    }
+
+    /// Mount a source in a priority group, like [`Loader::add_source_with_priority`].
+    ///
+    /// The discovered sources are in the [`Priority::Game`] group, sources added to the same group are searched after
+    /// them. Sources added to the builder are kept when the loader is [refreshed](Loader::refresh).
+    pub fn add_source_with_priority<S: AssetSource + Send + Sync + 'static>(
+        mut self,
+        source: S,
+        priority: Priority,
+    ) -> Self {
+        self.sources.push((Arc::new(source), priority));
+        self
+    }
+
+    /// Mount a directory after the default sources, in the order the sources are added to the builder.
+    ///
+    /// Building the loader fails if the path isn't a directory.
+    pub fn add_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.mounts.push(ExtraMount::Dir(path.as_ref().into()));
+        self
+    }
+
+    /// Mount a vpk file after the default sources, in the order the sources are added to the builder.
+    ///
+    /// Building the loader fails with [`LoaderError::Archive`] if the vpk can't be opened.
+    #[cfg(feature = "vpk")]
+    pub fn add_vpk<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.mounts.push(ExtraMount::Vpk(path.as_ref().into()));
+        self
+    }
+
+    /// Mount all vpk files and other [archives](Self::archive_extension) in a directory after the default sources,
+    /// in the order the sources are added to the builder.
+    ///
+    /// The directory itself isn't mounted and isn't searched recursively.
+    pub fn scan_vpks<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.mounts
+            .push(ExtraMount::ScanArchives(dir.as_ref().into()));
+        self
+    }
+
+    /// Refuse to mount directories or archives that are writable by users other than their owner and group.
+    ///
+    /// With this enabled, building the loader fails if any mounted path, or any directory it's in, is world-writable,
+    /// which would allow other users to inject assets or replace the mounted directory. Directories with the sticky bit
+    /// set, like `/tmp`, are allowed above the mounted paths since other users can't replace entries in them.
+    /// Directories inside a mounted directory are not checked. On platforms without unix permissions this only logs a
+    /// warning.
+    pub fn require_readonly(mut self, require_readonly: bool) -> Self {
+        self.require_readonly = require_readonly;
+        self
+    }
+
+    /// Open files ending with `extension` found in the searched game directories as sources.
+    ///
+    /// The extension is matched against the end of the file name, `.vpk` is registered by default to open both
+    /// directory vpk files and standalone vpk files. When multiple extensions match, the longest one is used, so
+    /// registering `dir.vpk` only overrides the factory for directory vpk files. Registering an extension again
+    /// replaces the previous factory. Files that fail to open are skipped with a warning.
+    ///
+    /// Numbered vpk data chunks, like `tf2_misc_001.vpk`, are never opened as archives.
+    pub fn archive_extension<F>(mut self, extension: &str, factory: F) -> Self
+    where
+        F: Fn(&Path) -> Result<Arc<dyn AssetSource + Send + Sync>, LoaderError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.archives
+            .retain(|(registered, _)| registered != extension);
+        self.archives.push((extension.into(), Arc::new(factory)));
+        self
+    }
+
+    /// The configured or auto-detected tf2 directory
+    pub(crate) fn resolved_tf2_dir(&self) -> Option<&Path> {
+        self.tf2_dir.as_deref()
+    }
+
+    /// Create the loader
+    pub fn build(mut self) -> Result<Loader, LoaderError> {
+        if self.tf2_dir.is_none() && (self.mount_defaults || self.mount_bin || self.mount_workshop)
+        {
+            let mut dirs = app_paths(self.layout.app_id)?.into_iter();
+            self.tf2_dir = dirs.next();
+            self.extra_tf2_dirs.splice(0..0, dirs);
+        }
+
+        let mut loader = Loader::empty();
+        for (label, source) in self.discover()? {
+            loader.push_source(source, Some(label));
+        }
+        for mount in loader.sources.iter_mut() {
+            mount.auto = true;
+            mount.priority = Priority::Game;
+        }
+        for (source, priority) in self.sources.iter() {
+            loader.push_with_priority(source.clone(), *priority);
+        }
+        loader.builder = Some(self);
+        Ok(loader)
+    }
+
+    /// Find all sources to mount, the tf2 directory has to be set already if the default sources are mounted.
+    ///
+    /// The sources are labeled with their path relative to the tf2 directory.
+    pub(crate) fn discover(&self) -> Result<Vec<(String, DiscoveredSource)>, LoaderError> {
+        let tf2_dir = self.tf2_dir.as_deref();
+
+        let mut sources = Vec::new();
+        if let Some(hd_dir) = &self.hd_content_dir {
+            if !hd_dir.is_dir() {
+                return Err(LoaderError::Other(format!(
+                    "hd content directory {} is not a directory",
+                    hd_dir.display()
+                )));
+            }
+            sources.push((hd_dir.clone(), Arc::new(hd_dir.clone()) as DiscoveredSource));
+        }
+
+        if self.mount_defaults {
+            sources.extend(self.root_search_paths(tf2_dir.ok_or(LoaderError::Tf2NotFound)?)?);
+            for extra_dir in &self.extra_tf2_dirs {
+                sources.extend(self.root_search_paths(extra_dir)?);
+            }
+        }
+
+        if self.mount_workshop {
+            let tf2_dir = tf2_dir.ok_or(LoaderError::Tf2NotFound)?;
+            if let Some(workshop) = WorkshopSource::for_tf2_dir(tf2_dir, self.layout.app_id) {
+                sources.push((workshop.dir().into(), Arc::new(workshop)));
+            }
+        }
+
+        for mount in &self.mounts {
+            match mount {
+                ExtraMount::Dir(dir) => {
+                    if !dir.is_dir() {
+                        return Err(LoaderError::Other(format!(
+                            "{} is not a directory",
+                            dir.display()
+                        )));
+                    }
+                    sources.push((dir.clone(), Arc::new(dir.clone())));
+                }
+                #[cfg(feature = "vpk")]
+                ExtraMount::Vpk(path) => {
+                    let vpk = open_vpk(path).map_err(|error| LoaderError::Archive {
+                        path: path.clone(),
+                        error: Box::new(error),
+                    })?;
+                    sources.push((path.clone(), Arc::new(vpk)));
+                }
+                ExtraMount::ScanArchives(dir) => {
+                    let mut archives = discover_archives(
+                        slice::from_ref(dir),
+                        &self.archives,
+                        self.strict_archives,
+                        &[],
+                    )?;
+                    archives.sort_by(|a, b| a.0.cmp(&b.0));
+                    sources.extend(archives);
+                }
+            }
+        }
+
+        if let Some(tf2_dir) = tf2_dir.filter(|_| self.mount_bin) {
+            for bin in [
+                tf2_dir.join(&self.layout.game_dir).join("bin"),
+                tf2_dir.join("bin"),
+            ] {
+                if bin.is_dir() {
+                    sources.push((bin.clone(), Arc::new(bin)));
+                }
+            }
+        }
+
+        if self.require_readonly {
+            for (path, _) in sources.iter() {
+                check_readonly(path)?;
+            }
+        }
+
+        Ok(sources
+            .into_iter()
+            .map(|(path, source)| {
+                let label = tf2_dir
+                    .into_iter()
+                    .chain(self.extra_tf2_dirs.iter().map(PathBuf::as_path))
+                    .find_map(|tf2_dir| path.strip_prefix(tf2_dir).ok())
+                    .unwrap_or(&path);
+                (label.to_string_lossy().replace('\\', "/"), source)
+            })
+            .filter(|(label, _)| self.filter.as_ref().is_none_or(|filter| filter(label)))
+            .collect())
+    }
+
+    /// Find the sources to mount from a tf2 directory, from `gameinfo.txt` if enabled
+    ///
+    /// Returns [`LoaderError::Tf2NotFound`] if the directory doesn't contain the game and `hl2` directories.
+    fn root_search_paths(
+        &self,
+        tf2_dir: &Path,
+    ) -> Result<Vec<(PathBuf, DiscoveredSource)>, LoaderError> {
+        if !tf2_dir.join(&self.layout.game_dir).is_dir() {
+            return Err(LoaderError::Tf2NotFound);
+        }
+        let gameinfo = if self.use_gameinfo {
+            let mut path_ids = vec!["game"];
+            if self.mount_platform {
+                path_ids.push("platform");
+            }
+            search_paths(
+                tf2_dir,
+                &self.layout.game_dir,
+                &path_ids,
+                &self.archives,
+                self.strict_archives,
+            )?
+        } else {
+            None
+        };
+        match gameinfo {
+            Some(mut search_paths) => {
+                if !self.mount_hl2 {
+                    for base_dir in &self.layout.base_dirs {
+                        let base_dir = tf2_dir.join(base_dir);
+                        search_paths.retain(|(path, _)| !path.starts_with(&base_dir));
+                    }
+                }
+                Ok(search_paths)
+            }
+            None => self.default_search_paths(tf2_dir),
+        }
+    }
+
+    /// Mount the game, base and `download` directories and their archives
+    fn default_search_paths(
+        &self,
+        tf2_dir: &Path,
+    ) -> Result<Vec<(PathBuf, DiscoveredSource)>, LoaderError> {
+        let tf_dir = tf2_dir.join(&self.layout.game_dir);
+        let mut base_dirs = Vec::new();
+        if self.mount_hl2 {
+            for base_dir in &self.layout.base_dirs {
+                let base_dir = tf2_dir.join(base_dir);
+                if !base_dir.is_dir() {
+                    return Err(LoaderError::Tf2NotFound);
+                }
+                // for half-life 2 itself the game directory is the `hl2` directory
+                if base_dir != tf_dir {
+                    base_dirs.push(base_dir);
+                }
+            }
+        }
+        let patterns = self.layout.archive_patterns()?;
+        let download = tf_dir.join("download");
+        let platform = tf2_dir.join("platform");
+        let platform = (self.mount_platform && platform.is_dir()).then_some(platform);
+
+        let mut archive_dirs = vec![tf_dir.clone()];
+        archive_dirs.extend(base_dirs.iter().cloned());
+        archive_dirs.extend(platform.clone());
+
+        // opening the archives in the custom directory can be just as slow as the game archives
+        let (custom, archives) = match self.scan_timeout {
+            None => (
+                self.custom_search_paths(&tf_dir, true)?,
+                discover_archives(
+                    &archive_dirs,
+                    &self.archives,
+                    self.strict_archives,
+                    &patterns,
+                )?,
+            ),
+            Some(timeout) => {
+                let builder = self.clone();
+                let custom_dir = tf_dir.clone();
+                let (tx, rx) = channel();
+                // the content folders are sent first, so running out of time doesn't need any filesystem access
+                spawn(move || {
+                    let plain = builder.custom_search_paths(&custom_dir, false);
+                    let failed = plain.is_err();
+                    if tx.send(Scan::Plain(plain)).is_err() || failed {
+                        return;
+                    }
+                    let scan = || -> Result<_, LoaderError> {
+                        Ok((
+                            builder.custom_search_paths(&custom_dir, true)?,
+                            discover_archives(
+                                &archive_dirs,
+                                &builder.archives,
+                                builder.strict_archives,
+                                &patterns,
+                            )?,
+                        ))
+                    };
+                    let _ = tx.send(Scan::Full(scan()));
+                });
+                let deadline = Instant::now() + timeout;
+                let mut plain = Vec::new();
+                loop {
+                    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(Scan::Plain(dirs)) => plain = dirs?,
+                        Ok(Scan::Full(scanned)) => break scanned?,
+                        Err(_) => {
+                            warn!(
+                                ?timeout,
+                                "archive discovery timed out, only mounting plain directories"
+                            );
+                            break (plain, Vec::new());
+                        }
+                    }
+                }
+            }
+        };
+
+        let mut sources = custom;
+        sources.push((tf_dir.clone(), Arc::new(tf_dir) as DiscoveredSource));
+        for base_dir in base_dirs {
+            sources.push((base_dir.clone(), Arc::new(base_dir)));
+        }
+
+        if self.mount_download && download.exists() {
+            sources.push((download.clone(), Arc::new(download)));
+        }
+
+        // the platform directory and its archives are searched after all game content
+        let (platform_archives, archives): (Vec<_>, Vec<_>) =
+            archives.into_iter().partition(|(path, _)| {
+                platform
+                    .as_ref()
+                    .is_some_and(|platform| path.starts_with(platform))
+            });
+        sources.extend(archives);
+
+        if let Some(platform) = platform {
+            sources.push((platform.clone(), Arc::new(platform)));
+        }
+        sources.extend(platform_archives);
+
+        Ok(sources)
+    }
+
+    /// Mount the content folders and archives in the `custom` directory, see [`mount_custom`](Self::mount_custom)
+    ///
+    /// Without `open_archives` only the content folders are mounted.
+    fn custom_search_paths(
+        &self,
+        game_dir: &Path,
+        open_archives: bool,
+    ) -> Result<Vec<(PathBuf, DiscoveredSource)>, LoaderError> {
+        let custom = game_dir.join("custom");
+        if !self.mount_custom || !custom.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut paths = custom
+            .read_dir()?
+            .filter_map(|item| item.ok())
+            .map(|item| item.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut sources = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                sources.push((path.clone(), Arc::new(path.clone()) as DiscoveredSource));
+                if open_archives {
+                    let mut archives = discover_archives(
+                        slice::from_ref(&path),
+                        &self.archives,
+                        self.strict_archives,
+                        &[],
+                    )?;
+                    archives.sort_by(|a, b| a.0.cmp(&b.0));
+                    sources.extend(archives);
+                }
+            } else if open_archives {
+                if let Some(source) = open_archive(&path, &self.archives, self.strict_archives)? {
+                    sources.push((path, source));
+                }
+            }
+        }
+        Ok(sources)
+    }
+}
+
+#[cfg(unix)]
+fn check_readonly(path: &Path) -> Result<(), LoaderError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if std::fs::metadata(path)?.permissions().mode() & 0o002 != 0 {
+        return Err(LoaderError::Other(format!(
+            "{} is writable by other users",
+            path.display()
+        )));
+    }
+    let path = path.canonicalize()?;
+    for dir in path.ancestors().skip(1) {
+        let mode = std::fs::metadata(dir)?.permissions().mode();
+        if mode & 0o002 != 0 && mode & 0o1000 == 0 {
+            return Err(LoaderError::Other(format!(
+                "{} is in {} which is writable by other users",
+                path.display(),
+                dir.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_readonly(path: &Path) -> Result<(), LoaderError> {
+    warn!(path = %path.display(), "can't verify that the path is read-only on this platform");
+    Ok(())
+}
+
+/// Open a file with the factory registered for its extension, if any
+///
+/// Archives that fail to open are skipped with a warning, unless `strict` is set.
+pub(crate) fn open_archive(
+    path: &Path,
+    archives: &[(String, ArchiveFactory)],
+    strict: bool,
+) -> Result<Option<DiscoveredSource>, LoaderError> {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(None);
+    };
+    if is_vpk_chunk(name) {
+        return Ok(None);
+    }
+    let Some((_, factory)) = archives
+        .iter()
+        .filter(|(ext, _)| name.ends_with(ext.as_str()))
+        .max_by_key(|(ext, _)| ext.len())
+    else {
+        return Ok(None);
+    };
+    match factory(path) {
+        Ok(source) => Ok(Some(source)),
+        Err(error) if strict => Err(LoaderError::Archive {
+            path: path.into(),
+            error: Box::new(error),
+        }),
+        Err(e) => {
+            warn!(error = ?e, path = %path.display(), "error while loading archive");
+            Ok(None)
+        }
+    }
+}
+
+/// Check if a file is a numbered data chunk of a directory vpk, like `tf2_misc_001.vpk`
+fn is_vpk_chunk(name: &str) -> bool {
+    name.strip_suffix(".vpk")
+        .and_then(|stem| stem.rsplit_once('_'))
+        .is_some_and(|(_, index)| index.len() == 3 && index.bytes().all(|c| c.is_ascii_digit()))
+}
+
+pub(crate) type DiscoveredSource = Arc<dyn AssetSource + Send + Sync>;
+
+/// Progress of a background scan started with a [`scan_timeout`](LoaderBuilder::scan_timeout)
+enum Scan {
+    /// The content folders in `custom`
+    Plain(Result<Vec<(PathBuf, DiscoveredSource)>, LoaderError>),
+    /// The content folders and archives in `custom`, followed by the archives in the game directories
+    #[allow(clippy::type_complexity)]
+    Full(
+        Result<
+            (
+                Vec<(PathBuf, DiscoveredSource)>,
+                Vec<(PathBuf, DiscoveredSource)>,
+            ),
+            LoaderError,
+        >,
+    ),
+}
+
+/// Open all archives in the directories with a file name matching one of the patterns, or all if there are none
+///
+/// Archives reachable through multiple paths, like symlinks to the same file, are only opened once.
+fn discover_archives(
+    dirs: &[PathBuf],
+    archives: &[(String, ArchiveFactory)],
+    strict: bool,
+    patterns: &[Pattern],
+) -> Result<Vec<(PathBuf, DiscoveredSource)>, LoaderError> {
+    let mut seen = HashSet::new();
+    let mut sources = Vec::new();
+    for dir in dirs {
+        for item in dir.read_dir()? {
+            let Ok(item) = item else {
+                continue;
+            };
+            let path = item.path();
+            let name = item.file_name();
+            if !matches_archive(patterns, &name.to_string_lossy()) {
+                continue;
+            }
+            if !seen.insert(canonicalize(&path).unwrap_or_else(|_| path.clone())) {
+                continue;
+            }
+            if let Some(source) = open_archive(&path, archives, strict)? {
+                sources.push((path, source));
+            }
+        }
+    }
+    Ok(sources)
+}
+
+#[test]
+fn test_mount_platform() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    create_dir_all(dir.path().join("platform/resource")).unwrap();
+    write(dir.path().join("platform/resource/platform.res"), "").unwrap();
+
+    let loader = LoaderBuilder::new().tf2_dir(dir.path()).build().unwrap();
+    assert!(!loader.exists("resource/platform.res").unwrap());
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .mount_platform(true)
+        .build()
+        .unwrap();
+    assert!(loader.exists("resource/platform.res").unwrap());
+
+    // the platform content has the lowest priority
+    write(dir.path().join("platform/resource/shared.res"), "platform").unwrap();
+    write(dir.path().join("tf/shared.res"), "tf").unwrap();
+    #[cfg(feature = "vpk")]
+    {
+        use crate::vpk_entry::build_test_vpk;
+        build_test_vpk(
+            &dir.path().join("hl2"),
+            "hl2_misc",
+            1,
+            &[("resource/shared.res", b"hl2 vpk")],
+        );
+        build_test_vpk(
+            &dir.path().join("platform"),
+            "platform_misc",
+            1,
+            &[
+                ("resource/shared.res", b"platform vpk"),
+                ("platform.vpk.res", b""),
+            ],
+        );
+    }
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .mount_platform(true)
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"tf".to_vec()), loader.load("shared.res").unwrap());
+    #[cfg(feature = "vpk")]
+    {
+        assert_eq!(
+            Some(b"hl2 vpk".to_vec()),
+            loader.load("resource/shared.res").unwrap()
+        );
+        assert!(loader.exists("platform.vpk.res").unwrap());
+        let sources: Vec<_> = loader.sources().collect();
+        assert_eq!(Some(&"vpk:platform_misc_dir.vpk"), sources.last());
+    }
+    #[cfg(not(feature = "vpk"))]
+    assert_eq!(
+        Some(b"platform".to_vec()),
+        loader.load("resource/shared.res").unwrap()
+    );
+}
+
+#[test]
+fn test_mount_bin() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf/bin")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    create_dir_all(dir.path().join("bin/x64")).unwrap();
+    write(dir.path().join("tf/bin/client.dll"), "tf").unwrap();
+    write(dir.path().join("bin/client.dll"), "bin").unwrap();
+    write(dir.path().join("bin/x64/engine.dll"), "").unwrap();
+
+    let loader = LoaderBuilder::new().tf2_dir(dir.path()).build().unwrap();
+    assert!(!loader.exists("client.dll").unwrap());
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .mount_bin(true)
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"tf".to_vec()), loader.load("client.dll").unwrap());
+    assert!(loader.exists("x64/engine.dll").unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_require_readonly() {
+    use std::fs::{Permissions, create_dir_all, set_permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    set_permissions(dir.path().join("tf"), Permissions::from_mode(0o755)).unwrap();
+    set_permissions(dir.path().join("hl2"), Permissions::from_mode(0o777)).unwrap();
+
+    let builder = LoaderBuilder::new().tf2_dir(dir.path());
+    assert!(builder.clone().build().is_ok());
+    assert!(builder.clone().require_readonly(true).build().is_err());
+
+    set_permissions(dir.path().join("hl2"), Permissions::from_mode(0o775)).unwrap();
+    assert!(builder.clone().require_readonly(true).build().is_ok());
+
+    // the directory containing the mounts can be used to replace them
+    set_permissions(dir.path(), Permissions::from_mode(0o777)).unwrap();
+    assert!(builder.clone().require_readonly(true).build().is_err());
+    set_permissions(dir.path(), Permissions::from_mode(0o1777)).unwrap();
+    assert!(builder.clone().require_readonly(true).build().is_ok());
+    set_permissions(dir.path(), Permissions::from_mode(0o700)).unwrap();
+    assert!(builder.require_readonly(true).build().is_ok());
+}
+
+#[test]
+fn test_hd_content_dir() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf/materials")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/materials/wall.vtf"), "base").unwrap();
+    write(dir.path().join("tf/materials/floor.vtf"), "base").unwrap();
+    let hd = tempfile::tempdir().unwrap();
+    create_dir_all(hd.path().join("materials")).unwrap();
+    write(hd.path().join("materials/wall.vtf"), "hd").unwrap();
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .hd_content_dir(hd.path())
+        .build()
+        .unwrap();
+    assert_eq!(
+        Some(b"hd".to_vec()),
+        loader.load("materials/wall.vtf").unwrap()
+    );
+    assert_eq!(
+        Some(b"base".to_vec()),
+        loader.load("materials/floor.vtf").unwrap()
+    );
+
+    let missing = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .hd_content_dir(hd.path().join("missing"))
+        .build();
+    assert!(missing.is_err());
+}
+
+#[test]
+fn test_archive_extension() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf/packed.pak/materials")).unwrap();
+    create_dir_all(dir.path().join("hl2/other.zip/materials")).unwrap();
+    write(dir.path().join("tf/packed.pak/materials/packed.vmt"), "").unwrap();
+    write(dir.path().join("hl2/other.zip/materials/other.vmt"), "").unwrap();
+
+    // directories standing in for archives
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .archive_extension(".pak", |path| Ok(Arc::new(path.to_path_buf())))
+        .build()
+        .unwrap();
+    assert!(loader.exists("materials/packed.vmt").unwrap());
+    assert!(!loader.exists("materials/other.vmt").unwrap());
+}
+
+#[test]
+fn test_app_game_dir() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("cstrike/download")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("cstrike/shared.txt"), "cstrike").unwrap();
+    write(dir.path().join("cstrike/download/download.txt"), "").unwrap();
+    write(dir.path().join("hl2/shared.txt"), "hl2").unwrap();
+    write(dir.path().join("hl2/hl2.txt"), "hl2").unwrap();
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .app(240, "cstrike")
+        .build()
+        .unwrap();
+    assert_eq!(
+        Some(b"cstrike".to_vec()),
+        loader.load("shared.txt").unwrap()
+    );
+    assert!(loader.exists("hl2.txt").unwrap());
+    assert!(loader.exists("download.txt").unwrap());
+
+    // half-life 2 itself only mounts the hl2 directory once
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .app(220, "hl2")
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"hl2".to_vec()), loader.load("shared.txt").unwrap());
+    assert_eq!(1, loader.source_count());
+}
+
+#[test]
+fn test_game_layout() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("garrysmod")).unwrap();
+    create_dir_all(dir.path().join("sourceengine")).unwrap();
+    write(dir.path().join("garrysmod/shared.txt"), "garrysmod").unwrap();
+    write(dir.path().join("sourceengine/shared.txt"), "sourceengine").unwrap();
+    write(dir.path().join("sourceengine/base.txt"), "").unwrap();
+
+    let layout = GameLayout::for_app(4000).unwrap();
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .layout(layout.clone())
+        .build()
+        .unwrap();
+    assert_eq!(
+        Some(b"garrysmod".to_vec()),
+        loader.load("shared.txt").unwrap()
+    );
+    assert!(loader.exists("base.txt").unwrap());
+    // the default layout looks for the tf and hl2 directories instead
+    assert!(LoaderBuilder::new().tf2_dir(dir.path()).build().is_err());
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .layout(layout.clone())
+        .mount_hl2(false)
+        .build()
+        .unwrap();
+    assert!(!loader.exists("base.txt").unwrap());
+
+    #[cfg(feature = "vpk")]
+    {
+        use crate::vpk_entry::build_test_vpk;
+        build_test_vpk(
+            &dir.path().join("sourceengine"),
+            "hl2_misc",
+            1,
+            &[("misc.txt", b"")],
+        );
+        build_test_vpk(
+            &dir.path().join("garrysmod"),
+            "extra",
+            1,
+            &[("extra.txt", b"")],
+        );
+        let loader = LoaderBuilder::new()
+            .tf2_dir(dir.path())
+            .layout(layout.clone())
+            .build()
+            .unwrap();
+        assert!(loader.exists("misc.txt").unwrap());
+        assert!(loader.exists("extra.txt").unwrap());
+
+        // only the archives matching the patterns are opened
+        let loader = LoaderBuilder::new()
+            .tf2_dir(dir.path())
+            .layout(GameLayout {
+                archives: vec![String::from("hl2_*_dir.vpk")],
+                ..layout
+            })
+            .build()
+            .unwrap();
+        assert!(loader.exists("misc.txt").unwrap());
+        assert!(!loader.exists("extra.txt").unwrap());
+    }
+}
+
+#[test]
+#[cfg(feature = "vpk")]
+fn test_custom_mounts() {
+    use crate::vpk_entry::build_test_vpk;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf/download")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    create_dir_all(dir.path().join("extra")).unwrap();
+    create_dir_all(dir.path().join("vpks")).unwrap();
+    write(dir.path().join("tf/download/download.txt"), "").unwrap();
+    write(dir.path().join("tf/shared.txt"), "tf").unwrap();
+    write(dir.path().join("extra/shared.txt"), "extra").unwrap();
+    build_test_vpk(&dir.path().join("vpks"), "a", 1, &[("a.txt", b"a")]);
+    build_test_vpk(&dir.path().join("vpks"), "b", 1, &[("b.txt", b"b")]);
+    build_test_vpk(dir.path(), "single", 1, &[("single.txt", b"single")]);
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .enable_download(false)
+        .add_dir(dir.path().join("extra"))
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"tf".to_vec()), loader.load("shared.txt").unwrap());
+    assert!(!loader.exists("download.txt").unwrap());
+
+    // without the defaults, the tf2 directory isn't needed
+    let loader = LoaderBuilder::new()
+        .mount_defaults(false)
+        .add_dir(dir.path().join("extra"))
+        .add_vpk(dir.path().join("single_dir.vpk"))
+        .scan_vpks(dir.path().join("vpks"))
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"extra".to_vec()), loader.load("shared.txt").unwrap());
+    assert!(loader.exists("single.txt").unwrap());
+    assert!(loader.exists("a.txt").unwrap());
+    assert!(loader.exists("b.txt").unwrap());
+    let mut sources = Vec::new();
+    loader.for_each_source(|_, source| sources.push(source.describe()));
+    assert_eq!(4, sources.len());
+    assert_eq!("vpk:a_dir.vpk", sources[2]);
+
+    let missing = LoaderBuilder::new()
+        .mount_defaults(false)
+        .add_vpk(dir.path().join("missing_dir.vpk"))
+        .build();
+    assert!(matches!(missing, Err(LoaderError::Archive { .. })));
+}
+
+#[test]
+#[cfg(feature = "vpk")]
+fn test_filter_sources() {
+    use crate::source::MemorySource;
+    use crate::vpk_entry::build_test_vpk;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/tf.txt"), "tf").unwrap();
+    write(dir.path().join("hl2/hl2.txt"), "hl2").unwrap();
+    build_test_vpk(&dir.path().join("tf"), "a", 1, &[("a.txt", b"a")]);
+    build_test_vpk(&dir.path().join("tf"), "b", 1, &[("b.txt", b"b")]);
+
+    let mut loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .mount_hl2(false)
+        .filter_sources(|label| !label.ends_with(".vpk") || label == "tf/a_dir.vpk")
+        .add_source(MemorySource::from_iter([("tf.txt", "base")]))
+        .add_source_with_priority(
+            MemorySource::from_iter([("override.txt", "override")]),
+            Priority::Override,
+        )
+        .build()
+        .unwrap();
+    let check = |loader: &Loader| {
+        assert_eq!(Some(b"tf".to_vec()), loader.load("tf.txt").unwrap());
+        assert!(!loader.exists("hl2.txt").unwrap());
+        assert!(loader.exists("a.txt").unwrap());
+        assert!(!loader.exists("b.txt").unwrap());
+        let sources: Vec<_> = loader.sources().collect();
+        assert_eq!(4, sources.len());
+        assert_eq!("memory", sources[0]);
+    };
+    check(&loader);
+    loader.refresh().unwrap();
+    check(&loader);
+}
+
+#[test]
+fn test_add_tf2_dir() {
+    use std::fs::{create_dir_all, write};
+
+    let modded = tempfile::tempdir().unwrap();
+    let vanilla = tempfile::tempdir().unwrap();
+    for dir in [modded.path(), vanilla.path()] {
+        create_dir_all(dir.join("tf")).unwrap();
+        create_dir_all(dir.join("hl2")).unwrap();
+    }
+    write(modded.path().join("tf/shared.txt"), "modded").unwrap();
+    write(vanilla.path().join("tf/shared.txt"), "vanilla").unwrap();
+    write(vanilla.path().join("hl2/vanilla.txt"), "vanilla").unwrap();
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(modded.path())
+        .add_tf2_dir(vanilla.path())
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"modded".to_vec()), loader.load("shared.txt").unwrap());
+    assert!(loader.exists("vanilla.txt").unwrap());
+    assert!(
+        loader
+            .load_from_labels("shared.txt", &["tf"])
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[test]
+#[cfg(all(unix, feature = "vpk"))]
+fn test_duplicate_archives() {
+    use crate::vpk_entry::build_test_vpk;
+    use std::fs::create_dir_all;
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let tf = dir.path().join("tf");
+    let hl2 = dir.path().join("hl2");
+    create_dir_all(&tf).unwrap();
+    create_dir_all(&hl2).unwrap();
+    build_test_vpk(&tf, "pak01", 1, &[("pak.txt", b"tf")]);
+    build_test_vpk(&hl2, "other", 1, &[("other.txt", b"hl2")]);
+    symlink(tf.join("pak01_dir.vpk"), hl2.join("pak01_dir.vpk")).unwrap();
+
+    let loader = LoaderBuilder::new().tf2_dir(dir.path()).build().unwrap();
+    assert_eq!(Some(b"tf".to_vec()), loader.load("pak.txt").unwrap());
+    assert!(loader.exists("other.txt").unwrap());
+    let vpks = loader
+        .sources()
+        .filter(|source| source.starts_with("vpk:"))
+        .count();
+    assert_eq!(2, vpks);
+}
+
+#[test]
+fn test_tf2_dir_layout() {
+    use std::fs::create_dir_all;
+
+    let dir = tempfile::tempdir().unwrap();
+    let missing = Loader::with_tf2_dir(dir.path().join("missing"));
+    assert!(matches!(missing, Err(LoaderError::Tf2NotFound)));
+
+    create_dir_all(dir.path().join("tf")).unwrap();
+    let no_hl2 = Loader::with_tf2_dir(dir.path());
+    assert!(matches!(no_hl2, Err(LoaderError::Tf2NotFound)));
+
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    assert!(Loader::with_tf2_dir(dir.path()).is_ok());
+}
+
+#[test]
+#[cfg(feature = "vpk")]
+fn test_mount_custom() {
+    use crate::vpk_entry::{build_test_single_vpk, build_test_vpk};
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let custom = dir.path().join("tf/custom");
+    create_dir_all(custom.join("b_hud/resource")).unwrap();
+    create_dir_all(custom.join("a_mod")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/shared.txt"), "tf").unwrap();
+    write(custom.join("b_hud/shared.txt"), "b_hud").unwrap();
+    write(custom.join("b_hud/resource/hud.res"), "hud").unwrap();
+    build_test_vpk(&custom.join("a_mod"), "mod", 1, &[("shared.txt", b"a_mod")]);
+    build_test_single_vpk(&custom.join("c_pack.vpk"), &[("pack.txt", b"pack")]);
+
+    let loader = LoaderBuilder::new().tf2_dir(dir.path()).build().unwrap();
+    assert_eq!(Some(b"a_mod".to_vec()), loader.load("shared.txt").unwrap());
+    assert_eq!(
+        Some(b"hud".to_vec()),
+        loader.load("resource/hud.res").unwrap()
+    );
+    assert_eq!(Some(b"pack".to_vec()), loader.load("pack.txt").unwrap());
+    // the archives in a content folder are mounted right after the folder
+    let sources = loader.sources().take(4).collect::<Vec<_>>();
+    assert!(sources[0].ends_with("a_mod"));
+    assert_eq!("vpk:mod_dir.vpk", sources[1]);
+    assert!(sources[2].ends_with("b_hud"));
+    assert_eq!("vpk:c_pack.vpk", sources[3]);
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(dir.path())
+        .mount_custom(false)
+        .build()
+        .unwrap();
+    assert_eq!(Some(b"tf".to_vec()), loader.load("shared.txt").unwrap());
+    assert!(!loader.exists("pack.txt").unwrap());
+}
@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// In-memory cache of loaded assets.
+///
+/// Once the total size of the cached assets exceeds the capacity, the least recently used assets are evicted.
+pub(crate) struct AssetCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+    /// paths that are currently being loaded, with the loaded data once the load is done
+    loading: Mutex<HashMap<String, Arc<LoadSlot>>>,
+}
+
+/// Holds the data of an in-progress load once it's done
+type LoadSlot = Mutex<Option<Arc<[u8]>>>;
+
+/// Result of [`AssetCache::get_or_load`]
+pub(crate) enum Lookup<T> {
+    /// The data was cached or loaded by a concurrent call
+    Cached(Arc<[u8]>),
+    Loaded(T),
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    /// cached paths by the tick they were last used at
+    recent: BTreeMap<u64, String>,
+    size: usize,
+    tick: u64,
+}
+
+struct CacheEntry {
+    data: Arc<[u8]>,
+    used: u64,
+}
+
+impl CacheState {
+    fn remove(&mut self, path: &str) -> bool {
+        match self.entries.remove(path) {
+            Some(entry) => {
+                self.recent.remove(&entry.used);
+                self.size -= entry.data.len();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl AssetCache {
+    pub fn new(capacity: usize) -> Self {
+        AssetCache {
+            capacity,
+            state: Mutex::default(),
+            loading: Mutex::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&self, path: &str) -> Option<Arc<[u8]>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = state.entries.get_mut(path)?;
+        let previous = std::mem::replace(&mut entry.used, tick);
+        let data = entry.data.clone();
+        if let Some(path) = state.recent.remove(&previous) {
+            state.recent.insert(tick, path);
+        }
+        Some(data)
+    }
+
+    pub fn insert(&self, path: String, data: Arc<[u8]>) {
+        if data.len() > self.capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(&path);
+        while state.size + data.len() > self.capacity {
+            let Some((_, oldest)) = state.recent.pop_first() else {
+                break;
+            };
+            state.remove(&oldest);
+        }
+        state.tick += 1;
+        let used = state.tick;
+        state.size += data.len();
+        state.recent.insert(used, path.clone());
+        state.entries.insert(path, CacheEntry { data, used });
+    }
+
+    /// Get the cached data for `path` or load it with `load` and add it to the cache.
+    ///
+    /// Concurrent calls for the same path wait for the first load to finish instead of loading the path again.
+    pub fn get_or_load<T, E, L, S>(
+        &self,
+        path: &str,
+        load: L,
+        share: S,
+    ) -> Result<Option<Lookup<T>>, E>
+    where
+        L: FnOnce() -> Result<Option<T>, E>,
+        S: FnOnce(&T) -> Arc<[u8]>,
+    {
+        let mut loading = self.loading.lock().unwrap();
+        if let Some(data) = self.get(path) {
+            return Ok(Some(Lookup::Cached(data)));
+        }
+        if let Some(slot) = loading.get(path).cloned() {
+            drop(loading);
+            let loaded = slot.lock().unwrap_or_else(PoisonError::into_inner).clone();
+            if let Some(data) = loaded {
+                return Ok(Some(Lookup::Cached(data)));
+            }
+            // the other load failed or didn't find the path
+            return Ok(load()?.map(|value| {
+                self.insert(path.into(), share(&value));
+                Lookup::Loaded(value)
+            }));
+        }
+
+        let slot = Arc::new(Mutex::new(None));
+        let mut loaded = slot.lock().unwrap();
+        loading.insert(path.into(), slot.clone());
+        drop(loading);
+        // removes the slot even if `load` panics, waiting calls then load the path themselves
+        let _loading = LoadingGuard { cache: self, path };
+
+        let result = load();
+        if let Ok(Some(value)) = &result {
+            let data = share(value);
+            self.insert(path.into(), data.clone());
+            *loaded = Some(data);
+        }
+        Ok(result?.map(Lookup::Loaded))
+    }
+
+    pub fn remove(&self, path: &str) -> bool {
+        self.state.lock().unwrap().remove(path)
+    }
+
+    /// Remove all entries for which `f` returns `false`, returning the number of removed entries
+    pub fn retain<F: FnMut(&str) -> bool>(&self, mut f: F) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let removed: Vec<String> = state
+            .entries
+            .keys()
+            .filter(|path| !f(path))
+            .cloned()
+            .collect();
+        for path in removed.iter() {
+            state.remove(path);
+        }
+        removed.len()
+    }
+
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = CacheState::default();
+    }
+
+    pub fn shrink_to_fit(&self) {
+        self.state.lock().unwrap().entries.shrink_to_fit();
+    }
+}
+
+/// Removes the slot of an in-progress load when dropped
+struct LoadingGuard<'a> {
+    cache: &'a AssetCache,
+    path: &'a str,
+}
+
+impl Drop for LoadingGuard<'_> {
+    fn drop(&mut self) {
+        self.cache
+            .loading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(self.path);
+    }
+}
+
+/// Bounded cache of normalized paths, keyed by the raw input path.
+///
+/// Once the capacity is reached, the cache is emptied before new paths are added.
+pub(crate) struct PathCache {
+    capacity: usize,
+    paths: RwLock<HashMap<String, Arc<str>>>,
+}
+
+impl PathCache {
+    pub fn new(capacity: usize) -> Self {
+        PathCache {
+            capacity,
+            paths: RwLock::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn shrink_to_fit(&self) {
+        self.paths.write().unwrap().shrink_to_fit();
+    }
+
+    /// Get the normalized form of `path`, computing it with `normalize` if it isn't cached yet
+    ///
+    /// Cached paths are looked up under a shared lock, so concurrent lookups don't block each other.
+    pub fn get_or_insert<F: FnOnce(&str) -> String>(&self, path: &str, normalize: F) -> Arc<str> {
+        if let Some(normalized) = self.paths.read().unwrap().get(path) {
+            return normalized.clone();
+        }
+        let normalized: Arc<str> = Arc::from(normalize(path));
+        if self.capacity == 0 {
+            return normalized;
+        }
+        let mut paths = self.paths.write().unwrap();
+        if paths.len() >= self.capacity {
+            paths.clear();
+        }
+        paths.insert(path.into(), normalized.clone());
+        normalized
+    }
+}
+
+#[test]
+fn test_cache_eviction() {
+    let cache = AssetCache::new(10);
+    cache.insert("a".into(), Arc::from(&[0; 4][..]));
+    cache.insert("b".into(), Arc::from(&[0; 4][..]));
+    assert!(cache.get("a").is_some());
+
+    // "b" is the least recently used
+    cache.insert("c".into(), Arc::from(&[0; 4][..]));
+    assert!(cache.get("a").is_some());
+    assert!(cache.get("b").is_none());
+    assert!(cache.get("c").is_some());
+
+    // too large to ever be cached
+    cache.insert("d".into(), Arc::from(&[0; 11][..]));
+    assert!(cache.get("d").is_none());
+
+    assert!(cache.remove("a"));
+    assert!(!cache.remove("a"));
+    assert_eq!(1, cache.retain(|path| path != "c"));
+    assert!(cache.get("c").is_none());
+}
+
+#[test]
+fn test_cache_get_or_load() {
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let cache = AssetCache::new(100);
+    let loads = AtomicUsize::new(0);
+    let barrier = Barrier::new(8);
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                barrier.wait();
+                let found = cache.get_or_load(
+                    "a",
+                    || {
+                        loads.fetch_add(1, Ordering::SeqCst);
+                        thread::sleep(std::time::Duration::from_millis(50));
+                        Ok::<_, ()>(Some(vec![1, 2, 3]))
+                    },
+                    |data| Arc::from(data.as_slice()),
+                );
+                let data = match found.unwrap().unwrap() {
+                    Lookup::Cached(data) => data.to_vec(),
+                    Lookup::Loaded(data) => data,
+                };
+                assert_eq!(vec![1, 2, 3], data);
+            });
+        }
+    });
+    assert_eq!(1, loads.load(Ordering::SeqCst));
+    assert!(cache.get("a").is_some());
+
+    // failed and missing loads aren't cached
+    assert!(
+        cache
+            .get_or_load("b", || Err::<Option<Vec<u8>>, _>(()), |_| unreachable!())
+            .is_err()
+    );
+    assert!(
+        cache
+            .get_or_load("b", || Ok::<Option<Vec<u8>>, ()>(None), |_| unreachable!())
+            .unwrap()
+            .is_none()
+    );
+    assert!(cache.get("b").is_none());
+
+    // a panicking load doesn't block later loads
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cache.get_or_load(
+            "c",
+            || -> Result<Option<Vec<u8>>, ()> { panic!("load failed") },
+            |_| unreachable!(),
+        )
+    }));
+    assert!(panicked.is_err());
+    assert!(matches!(
+        cache.get_or_load(
+            "c",
+            || Ok::<_, ()>(Some(vec![1])),
+            |data| Arc::from(data.as_slice())
+        ),
+        Ok(Some(Lookup::Loaded(_)))
+    ));
+    assert!(cache.get("c").is_some());
+}
+
+#[test]
+fn test_path_cache() {
+    let cache = PathCache::new(2);
+    assert_eq!("a", &*cache.get_or_insert("x/../a", |_| "a".into()));
+    // served from the cache
+    assert_eq!("a", &*cache.get_or_insert("x/../a", |_| unreachable!()));
+    cache.get_or_insert("b", |path| path.into());
+    // full, the cache is emptied
+    cache.get_or_insert("c", |path| path.into());
+    assert_eq!("d", &*cache.get_or_insert("x/../a", |_| "d".into()));
+}
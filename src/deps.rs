@@ -0,0 +1,470 @@
+use crate::kv;
+use crate::{Loader, LoaderError, clean_path, strip_prefix_ignore_case, strip_suffix_ignore_case};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Maximum number of references followed from the root asset
+const MAX_DEPTH: usize = 16;
+
+/// Characters that can prefix sound names to control playback, these are not part of the file name
+const SOUND_CHARS: &[char] = &[
+    '*', '#', '@', '>', '<', '^', ')', '(', '}', '$', '!', '?', '&', '~', '+',
+];
+
+const SOUND_EXTENSIONS: &[&str] = &[".wav", ".mp3"];
+
+/// Files that are loaded alongside a model
+const MODEL_SIBLINGS: &[&str] = &[
+    ".vvd",
+    ".dx90.vtx",
+    ".dx80.vtx",
+    ".sw.vtx",
+    ".vtx",
+    ".phy",
+    ".ani",
+];
+
+/// Material parameters that reference textures
+const TEXTURE_PARAMS: &[&str] = &[
+    "$basetexture",
+    "$basetexture2",
+    "$bumpmap",
+    "$bumpmap2",
+    "$normalmap",
+    "$detail",
+    "$envmap",
+    "$envmapmask",
+    "$phongexponenttexture",
+    "$phongwarptexture",
+    "$lightwarptexture",
+    "$selfillummask",
+    "$blendmodulatetexture",
+    "$dudvmap",
+    "$iris",
+    "$corneatexture",
+    "$ambientoccltexture",
+];
+
+/// Material parameters that reference other materials
+const MATERIAL_PARAMS: &[&str] = &["include", "$bottommaterial", "$underwateroverlay"];
+
+/// All assets referenced by an asset, directly or indirectly.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    /// Every resolved asset in the graph, mapped to the assets it references directly
+    pub assets: BTreeMap<String, Vec<String>>,
+    /// References that couldn't be resolved to an existing asset, as the full path they were looked up by
+    pub missing: BTreeSet<String>,
+}
+
+impl Loader {
+    /// Load a sound by name, as referenced by sound scripts and entities.
+    ///
+    /// The `sound/` prefix is added when missing, and the common audio extensions are tried if the name has none.
+    /// Leading sound characters like `)` or `^` that control playback are ignored.
+    pub fn load_sound(&self, name: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        match self.resolve_sound(name)? {
+            Some(path) => self.load(&path),
+            None => Ok(None),
+        }
+    }
+
+    fn resolve_sound(&self, name: &str) -> Result<Option<String>, LoaderError> {
+        for path in sound_paths(name) {
+            if self.exists(&path)? {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Load a material by name, as referenced by models and other materials.
+    ///
+    /// The `materials/` prefix and `.vmt` extension are added when missing.
+    pub fn load_material(&self, name: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        match self.resolve_material(name)? {
+            Some(path) => self.load(&path),
+            None => Ok(None),
+        }
+    }
+
+    /// Load a model together with the files the engine loads alongside it, like `.vvd`, `.dx90.vtx` and `.phy`.
+    ///
+    /// The `models/` prefix and `.mdl` extension are added when missing. The files are keyed by their extension
+    /// without the leading `.`, like `mdl` or `dx90.vtx`, files that don't exist are left out.
+    /// Returns an empty map if the model itself doesn't exist.
+    pub fn load_model_group(&self, name: &str) -> Result<BTreeMap<String, Vec<u8>>, LoaderError> {
+        let name = name.trim_start_matches('/').replace('\\', "/");
+        let stem = strip_suffix_ignore_case(&name, ".mdl").unwrap_or(&name);
+        let stem = strip_prefix_ignore_case(stem, "models/").unwrap_or(stem);
+        let base = format!("models/{stem}");
+
+        let mut group = BTreeMap::new();
+        let Some(model) = self.load(&format!("{base}.mdl"))? else {
+            return Ok(group);
+        };
+        group.insert(String::from("mdl"), model);
+        for suffix in MODEL_SIBLINGS {
+            if let Some(data) = self.load(&format!("{base}{suffix}"))? {
+                group.insert(suffix[1..].to_string(), data);
+            }
+        }
+        Ok(group)
+    }
+
+    fn resolve_material(&self, name: &str) -> Result<Option<String>, LoaderError> {
+        let path = material_path(name);
+        Ok(self.exists(&path)?.then_some(path))
+    }
+
+    /// Resolve every asset an asset pulls in, following models to their materials, materials to their textures
+    /// and sound scripts to their sounds.
+    ///
+    /// Each asset is only visited once, and references are followed up to a fixed depth.
+    pub fn dependency_graph(&self, root: &str) -> Result<DependencyGraph, LoaderError> {
+        let mut graph = DependencyGraph::default();
+        let root = clean_path(root).into_owned();
+        if !self.exists(&root)? {
+            graph.missing.insert(root);
+            return Ok(graph);
+        }
+
+        let mut queue = VecDeque::from([(root, 0)]);
+        while let Some((path, depth)) = queue.pop_front() {
+            if graph.assets.contains_key(&path) {
+                continue;
+            }
+            let references = if depth < MAX_DEPTH {
+                self.references(&path, &mut graph.missing)?
+            } else {
+                Vec::new()
+            };
+            for reference in references.iter() {
+                if !graph.assets.contains_key(reference) {
+                    queue.push_back((reference.clone(), depth + 1));
+                }
+            }
+            graph.assets.insert(path, references);
+        }
+        Ok(graph)
+    }
+
+    fn references(
+        &self,
+        path: &str,
+        missing: &mut BTreeSet<String>,
+    ) -> Result<Vec<String>, LoaderError> {
+        let lower = path.to_ascii_lowercase();
+        if let Some(stem) = lower.strip_suffix(".mdl") {
+            self.model_references(path, &path[..stem.len()], missing)
+        } else if lower.ends_with(".vmt") {
+            self.material_references(path, missing)
+        } else if lower.ends_with(".txt") {
+            self.script_references(path, missing)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn model_references(
+        &self,
+        path: &str,
+        stem: &str,
+        missing: &mut BTreeSet<String>,
+    ) -> Result<Vec<String>, LoaderError> {
+        let mut references: Vec<String> = self
+            .load_variants(stem, MODEL_SIBLINGS)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+
+        let Some(data) = self.load(path)? else {
+            return Ok(references);
+        };
+        let Some((textures, dirs)) = model_materials(&data) else {
+            return Ok(references);
+        };
+        for texture in textures {
+            let mut found = None;
+            for dir in dirs.iter() {
+                if let Some(material) = self.resolve_material(&format!("{dir}{texture}"))? {
+                    found = Some(material);
+                    break;
+                }
+            }
+            match found {
+                Some(material) => references.push(material),
+                None => {
+                    let dir = dirs.first().map(String::as_str).unwrap_or_default();
+                    missing.insert(material_path(&format!("{dir}{texture}")));
+                }
+            }
+        }
+        Ok(references)
+    }
+
+    fn material_references(
+        &self,
+        path: &str,
+        missing: &mut BTreeSet<String>,
+    ) -> Result<Vec<String>, LoaderError> {
+        let Some(data) = self.load(path)? else {
+            return Ok(Vec::new());
+        };
+        let material = kv::parse(&String::from_utf8_lossy(&data));
+        let mut references = Vec::new();
+        for (key, value) in kv::pairs(&material) {
+            let key = key.to_ascii_lowercase();
+            if TEXTURE_PARAMS.contains(&key.as_str()) {
+                if value.starts_with("_rt_") || value.eq_ignore_ascii_case("env_cubemap") {
+                    continue;
+                }
+                match self.resolve_texture(value)? {
+                    Some(texture) => references.push(texture),
+                    None => {
+                        missing.insert(texture_path(value));
+                    }
+                }
+            } else if MATERIAL_PARAMS.contains(&key.as_str()) {
+                match self.resolve_material(value)? {
+                    Some(material) => references.push(material),
+                    None => {
+                        missing.insert(material_path(value));
+                    }
+                }
+            }
+        }
+        Ok(references)
+    }
+
+    fn script_references(
+        &self,
+        path: &str,
+        missing: &mut BTreeSet<String>,
+    ) -> Result<Vec<String>, LoaderError> {
+        let Some(data) = self.load(path)? else {
+            return Ok(Vec::new());
+        };
+        let script = kv::parse(&String::from_utf8_lossy(&data));
+        let mut references = Vec::new();
+        for (key, value) in kv::pairs(&script) {
+            if key.eq_ignore_ascii_case("wave") {
+                match self.resolve_sound(value)? {
+                    Some(sound) => references.push(sound),
+                    None => {
+                        missing.extend(sound_paths(value).into_iter().next());
+                    }
+                }
+            }
+        }
+        Ok(references)
+    }
+}
+
+/// Path of a material, adding the `materials/` prefix and `.vmt` extension when missing
+fn material_path(name: &str) -> String {
+    let name = name.trim_start_matches('/').replace('\\', "/");
+    let stem = strip_suffix_ignore_case(&name, ".vmt").unwrap_or(&name);
+    let stem = strip_prefix_ignore_case(stem, "materials/").unwrap_or(stem);
+    clean_path(&format!("materials/{stem}.vmt")).into_owned()
+}
+
+/// Path of a texture in the materials directory, adding the `materials/` prefix and `.vtf` extension when missing
+fn texture_path(name: &str) -> String {
+    let name = name.trim_start_matches('/').replace('\\', "/");
+    let stem = strip_suffix_ignore_case(&name, ".vtf").unwrap_or(&name);
+    let stem = strip_prefix_ignore_case(stem, "materials/").unwrap_or(stem);
+    clean_path(&format!("materials/{stem}.vtf")).into_owned()
+}
+
+/// Candidate paths of a sound, in the order they are tried
+fn sound_paths(name: &str) -> Vec<String> {
+    let name = name.trim_start_matches(SOUND_CHARS).replace('\\', "/");
+    let name = strip_prefix_ignore_case(&name, "sound/").unwrap_or(&name);
+    let has_extension = SOUND_EXTENSIONS
+        .iter()
+        .any(|ext| strip_suffix_ignore_case(name, ext).is_some());
+    let extensions = if has_extension {
+        &[""]
+    } else {
+        SOUND_EXTENSIONS
+    };
+    extensions
+        .iter()
+        .map(|ext| clean_path(&format!("sound/{name}{ext}")).into_owned())
+        .collect()
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + 4)?;
+    usize::try_from(i32::from_le_bytes(bytes.try_into().unwrap())).ok()
+}
+
+fn read_cstr(data: &[u8], offset: usize) -> Option<String> {
+    let data = data.get(offset..)?;
+    let end = data.iter().position(|c| *c == 0)?;
+    Some(String::from_utf8_lossy(&data[..end]).replace('\\', "/"))
+}
+
+/// Read the texture names and material directories from a model's header
+fn model_materials(data: &[u8]) -> Option<(Vec<String>, Vec<String>)> {
+    const TEXTURE_SIZE: usize = 64;
+
+    if data.get(0..4)? != b"IDST" {
+        return None;
+    }
+    let texture_count = read_i32(data, 204)?;
+    let texture_index = read_i32(data, 208)?;
+    let dir_count = read_i32(data, 212)?;
+    let dir_index = read_i32(data, 216)?;
+
+    let textures = (0..texture_count)
+        .map(|i| {
+            let texture = texture_index + i * TEXTURE_SIZE;
+            read_cstr(data, texture + read_i32(data, texture)?)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let dirs = (0..dir_count)
+        .map(|i| {
+            let dir = read_cstr(data, read_i32(data, dir_index + i * 4)?)?;
+            Some(match dir.trim_start_matches('/') {
+                "" => String::new(),
+                dir if dir.ends_with('/') => dir.to_string(),
+                dir => format!("{dir}/"),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((textures, dirs))
+}
+
+#[cfg(test)]
+fn build_test_model(texture: &str, dir: &str) -> Vec<u8> {
+    let mut data = vec![0; 220];
+    data[0..4].copy_from_slice(b"IDST");
+    let texture_index = 220;
+    let dir_index = texture_index + 64;
+    let name_offset = dir_index + 4;
+    let dir_offset = name_offset + texture.len() + 1;
+    for (offset, value) in [(204, 1), (208, texture_index), (212, 1), (216, dir_index)] {
+        data[offset..offset + 4].copy_from_slice(&(value as i32).to_le_bytes());
+    }
+    data.extend_from_slice(&((name_offset - texture_index) as i32).to_le_bytes());
+    data.resize(dir_index, 0);
+    data.extend_from_slice(&(dir_offset as i32).to_le_bytes());
+    data.extend_from_slice(texture.as_bytes());
+    data.push(0);
+    data.extend_from_slice(dir.as_bytes());
+    data.push(0);
+    data
+}
+
+#[test]
+fn test_load_model_group() {
+    use crate::source::MemorySource;
+
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([
+        ("models/props/crate.mdl", "mdl"),
+        ("models/props/crate.vvd", "vvd"),
+        ("models/props/crate.dx90.vtx", "vtx"),
+        ("materials/models/props/crate.vmt", "vmt"),
+    ]));
+
+    let group = loader.load_model_group("props/crate").unwrap();
+    assert_eq!(
+        vec!["dx90.vtx", "mdl", "vvd"],
+        group.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(b"vtx".as_slice(), group["dx90.vtx"]);
+    assert_eq!(
+        group,
+        loader.load_model_group("models\\props\\crate.mdl").unwrap()
+    );
+    assert!(loader.load_model_group("props/missing").unwrap().is_empty());
+
+    assert_eq!(
+        Some(b"vmt".to_vec()),
+        loader.load_material("models/props/crate").unwrap()
+    );
+    assert_eq!(
+        Some(b"vmt".to_vec()),
+        loader
+            .load_material("materials/models/props/crate.vmt")
+            .unwrap()
+    );
+    assert!(
+        loader
+            .load_material("models/props/missing")
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_dependency_graph() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    create_dir_all(root.join("models/props")).unwrap();
+    create_dir_all(root.join("materials/models/props")).unwrap();
+    create_dir_all(root.join("scripts")).unwrap();
+    create_dir_all(root.join("sound/ambient")).unwrap();
+    write(
+        root.join("models/props/crate.mdl"),
+        build_test_model("crate", "models\\props\\"),
+    )
+    .unwrap();
+    write(root.join("models/props/crate.vvd"), "").unwrap();
+    write(
+        root.join("materials/models/props/crate.vmt"),
+        r#""VertexLitGeneric" { "$basetexture" "models/props/crate" "$bumpmap" "models/props/missing" "$envmap" "env_cubemap" "include" "materials/models/props/crate.vmt" }"#,
+    )
+    .unwrap();
+    write(root.join("materials/models/props/crate.vtf"), "").unwrap();
+    write(
+        root.join("scripts/soundscape.txt"),
+        r#""ambient.wind" { "playlooping" { "wave" ")ambient/wind.wav" } "rndwave" { "wave" "ambient/gust" } }"#,
+    )
+    .unwrap();
+    write(root.join("sound/ambient/wind.wav"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(root.to_path_buf());
+
+    let graph = loader.dependency_graph("models/props/crate.mdl").unwrap();
+    assert_eq!(
+        vec![
+            "materials/models/props/crate.vmt",
+            "materials/models/props/crate.vtf",
+            "models/props/crate.mdl",
+            "models/props/crate.vvd",
+        ],
+        graph.assets.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["models/props/crate.vvd", "materials/models/props/crate.vmt"],
+        graph.assets["models/props/crate.mdl"]
+    );
+    assert_eq!(
+        vec!["materials/models/props/missing.vtf"],
+        graph.missing.iter().collect::<Vec<_>>()
+    );
+
+    let graph = loader.dependency_graph("scripts/soundscape.txt").unwrap();
+    assert_eq!(
+        vec!["sound/ambient/wind.wav"],
+        graph.assets["scripts/soundscape.txt"]
+    );
+    assert_eq!(
+        vec!["sound/ambient/gust.wav"],
+        graph.missing.iter().collect::<Vec<_>>()
+    );
+
+    assert!(loader.load_sound("^ambient/wind").unwrap().is_some());
+    assert!(
+        loader
+            .load_sound("sound/ambient/wind.wav")
+            .unwrap()
+            .is_some()
+    );
+}
@@ -0,0 +1,103 @@
+use crate::kv::{self, Value};
+use crate::{Loader, LoaderError, strip_suffix_ignore_case};
+use std::collections::BTreeSet;
+
+const FONT_EXTENSIONS: &[&str] = &[".ttf", ".otf", ".vfont"];
+
+const CLIENT_SCHEME: &str = "resource/ClientScheme.res";
+
+impl Loader {
+    /// List the font files used by the game's hud.
+    ///
+    /// This includes all font files in the `resource` directory and the custom fonts referenced by
+    /// `resource/ClientScheme.res` that exist in any source. The returned paths are sorted and de-duplicated.
+    pub fn list_fonts(&self) -> Result<Vec<String>, LoaderError> {
+        let mut fonts: BTreeSet<String> = self
+            .list("resource/")?
+            .into_iter()
+            .filter(|path| is_font(path))
+            .collect();
+
+        if let Some(scheme) = self.load(CLIENT_SCHEME)? {
+            let scheme = String::from_utf8_lossy(&scheme);
+            for font in scheme_fonts(&kv::parse(&scheme)) {
+                let font = font.replace('\\', "/");
+                if self.exists(&font)? {
+                    fonts.insert(font);
+                }
+            }
+        }
+        Ok(fonts.into_iter().collect())
+    }
+}
+
+fn is_font(path: &str) -> bool {
+    FONT_EXTENSIONS
+        .iter()
+        .any(|ext| strip_suffix_ignore_case(path, ext).is_some())
+}
+
+/// Get the font files from the `CustomFontFiles` section of a scheme file
+fn scheme_fonts(entries: &[(String, Value)]) -> Vec<&str> {
+    let mut fonts = Vec::new();
+    for (key, value) in entries {
+        let Value::Block(entries) = value else {
+            continue;
+        };
+        if key.eq_ignore_ascii_case("CustomFontFiles") {
+            fonts.extend(
+                kv::pairs(entries)
+                    .into_iter()
+                    .map(|(_, value)| value)
+                    .filter(|value| is_font(value)),
+            );
+        } else {
+            fonts.extend(scheme_fonts(entries));
+        }
+    }
+    fonts
+}
+
+#[test]
+fn test_list_fonts() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("resource/fonts")).unwrap();
+    write(dir.path().join("resource/tf2.ttf"), "").unwrap();
+    write(dir.path().join("resource/fonts/hud.otf"), "").unwrap();
+    write(dir.path().join("resource/tf2.res"), "").unwrap();
+    create_dir_all(dir.path().join("custom/fonts")).unwrap();
+    write(dir.path().join("custom/fonts/custom.ttf"), "").unwrap();
+    write(
+        dir.path().join("resource/clientscheme.res"),
+        r#"
+        "Scheme"
+        {
+            "Fonts" { "Default" { "1" { "name" "Verdana" } } }
+            "CustomFontFiles"
+            {
+                "1" "resource/tf2.ttf"
+                "2"
+                {
+                    "font" "custom\fonts\custom.ttf"
+                    "name" "Custom"
+                }
+                "3" "resource/missing.ttf"
+            }
+        }
+        "#,
+    )
+    .unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    assert_eq!(
+        vec![
+            "custom/fonts/custom.ttf",
+            "resource/fonts/hud.otf",
+            "resource/tf2.ttf",
+        ],
+        loader.list_fonts().unwrap()
+    );
+}
@@ -0,0 +1,242 @@
+//! Search paths declared in `gameinfo.txt`
+
+use crate::LoaderError;
+use crate::builder::{ArchiveFactory, DiscoveredSource, open_archive};
+use crate::kv::{Value, parse};
+use glob::{MatchOptions, Pattern};
+use std::collections::HashSet;
+use std::fs::{canonicalize, read_to_string};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const GAMEINFO_FILE: &str = "gameinfo.txt";
+const GAMEINFO_PATH_TOKEN: &str = "|gameinfo_path|";
+const ENGINE_PATH_TOKEN: &str = "|all_source_engine_paths|";
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Resolve the search paths declared in the `gameinfo.txt` of the game directory in the declared order
+///
+/// Only search paths for any of the `path_ids` are included, returns `None` if there is no `gameinfo.txt`.
+pub(crate) fn search_paths(
+    tf2_dir: &Path,
+    game_dir: &str,
+    path_ids: &[&str],
+    archives: &[(String, ArchiveFactory)],
+    strict: bool,
+) -> Result<Option<Vec<(PathBuf, DiscoveredSource)>>, LoaderError> {
+    let gameinfo_dir = tf2_dir.join(game_dir);
+    let gameinfo_path = gameinfo_dir.join(GAMEINFO_FILE);
+    let text = match read_to_string(&gameinfo_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let entries = parse(&text);
+    let search_paths = ["GameInfo", "FileSystem", "SearchPaths"]
+        .iter()
+        .try_fold(entries.as_slice(), |entries, key| block(entries, key))
+        .unwrap_or_default();
+
+    let mut seen = HashSet::new();
+    let mut sources = Vec::new();
+    for (key, value) in search_paths {
+        let Value::String(path) = value else {
+            continue;
+        };
+        if !key.split('+').any(|id| {
+            path_ids
+                .iter()
+                .any(|path_id| id.eq_ignore_ascii_case(path_id))
+        }) {
+            continue;
+        }
+        let path = resolve(path, tf2_dir, &gameinfo_dir);
+        for (path, source) in open_search_path(&path, archives, strict)? {
+            if seen.insert(canonicalize(&path).unwrap_or_else(|_| path.clone())) {
+                sources.push((path, source));
+            }
+        }
+    }
+    Ok(Some(sources))
+}
+
+fn block<'a>(entries: &'a [(String, Value)], key: &str) -> Option<&'a [(String, Value)]> {
+    entries.iter().find_map(|(name, value)| match value {
+        Value::Block(entries) if name.eq_ignore_ascii_case(key) => Some(entries.as_slice()),
+        _ => None,
+    })
+}
+
+/// Resolve the path tokens in a search path
+fn resolve(path: &str, tf2_dir: &Path, gameinfo_dir: &Path) -> PathBuf {
+    let (base, rest) = if let Some(rest) = strip_token(path, GAMEINFO_PATH_TOKEN) {
+        (gameinfo_dir, rest)
+    } else if let Some(rest) = strip_token(path, ENGINE_PATH_TOKEN) {
+        (tf2_dir, rest)
+    } else {
+        (tf2_dir, path)
+    };
+    let rest = rest.trim_end_matches("/.").trim_start_matches(['/', '\\']);
+    match rest {
+        "" | "." => base.to_path_buf(),
+        rest => base.join(rest),
+    }
+}
+
+fn strip_token<'a>(path: &'a str, token: &str) -> Option<&'a str> {
+    let prefix = path.get(..token.len())?;
+    prefix
+        .eq_ignore_ascii_case(token)
+        .then(|| &path[token.len()..])
+}
+
+/// Open all sources for a single search path, skipping paths that don't exist
+///
+/// The last component of the path can contain wildcards, like `custom/*` or `hl2/hl2_*.vpk`.
+fn open_search_path(
+    path: &Path,
+    archives: &[(String, ArchiveFactory)],
+    strict: bool,
+) -> Result<Vec<(PathBuf, DiscoveredSource)>, LoaderError> {
+    let file_name = path.file_name().and_then(|name| name.to_str());
+    if let Some(pattern) = file_name.filter(|name| name.contains(['*', '?'])) {
+        let pattern = Pattern::new(pattern)?;
+        let Some(dir) = path.parent().filter(|dir| dir.is_dir()) else {
+            return Ok(Vec::new());
+        };
+        let mut paths = dir
+            .read_dir()?
+            .filter_map(|item| item.ok())
+            .filter(|item| {
+                item.file_name()
+                    .to_str()
+                    .is_some_and(|name| pattern.matches_with(name, MATCH_OPTIONS))
+            })
+            .map(|item| item.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        return paths
+            .into_iter()
+            .filter_map(|path| open_path(path, archives, strict).transpose())
+            .collect();
+    }
+
+    // directory vpk files are declared without their `_dir` suffix
+    let path = match path.to_str().and_then(|path| path.strip_suffix(".vpk")) {
+        Some(base) if Path::new(&format!("{base}_dir.vpk")).is_file() => {
+            PathBuf::from(format!("{base}_dir.vpk"))
+        }
+        _ => path.to_path_buf(),
+    };
+    Ok(open_path(path, archives, strict)?.into_iter().collect())
+}
+
+fn open_path(
+    path: PathBuf,
+    archives: &[(String, ArchiveFactory)],
+    strict: bool,
+) -> Result<Option<(PathBuf, DiscoveredSource)>, LoaderError> {
+    if path.is_dir() {
+        Ok(Some((path.clone(), Arc::new(path))))
+    } else if path.is_file() {
+        Ok(open_archive(&path, archives, strict)?.map(|source| (path, source)))
+    } else {
+        Ok(None)
+    }
+}
+
+#[test]
+fn test_gameinfo_search_paths() {
+    use crate::Loader;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf/custom/b_mod")).unwrap();
+    create_dir_all(dir.path().join("tf/custom/a_mod")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/custom/a_mod/shared.txt"), "a_mod").unwrap();
+    write(dir.path().join("tf/custom/b_mod/shared.txt"), "b_mod").unwrap();
+    write(dir.path().join("tf/custom/b_mod/custom.txt"), "b_mod").unwrap();
+    write(dir.path().join("hl2/custom.txt"), "hl2").unwrap();
+    write(dir.path().join("hl2/hl2.txt"), "hl2").unwrap();
+    write(dir.path().join("tf/hl2.txt"), "tf").unwrap();
+
+    // without gameinfo the default layout is used, where tf comes before hl2
+    let loader = Loader::from_gameinfo(dir.path()).unwrap();
+    assert_eq!(Some(b"tf".to_vec()), loader.load("hl2.txt").unwrap());
+    assert_eq!(Some(b"a_mod".to_vec()), loader.load("shared.txt").unwrap());
+
+    write(
+        dir.path().join("tf/gameinfo.txt"),
+        r#"
+        "GameInfo"
+        {
+            FileSystem
+            {
+                SearchPaths
+                {
+                    game+mod+custom_mod    tf/custom/*
+                    game                   |all_source_engine_paths|hl2
+                    game+mod               |gameinfo_path|.
+                    game+game_write        tf
+                    gamebin                tf/bin
+                }
+            }
+        }
+        "#,
+    )
+    .unwrap();
+
+    let loader = Loader::from_gameinfo(dir.path()).unwrap();
+    assert_eq!(Some(b"a_mod".to_vec()), loader.load("shared.txt").unwrap());
+    assert_eq!(Some(b"b_mod".to_vec()), loader.load("custom.txt").unwrap());
+    assert_eq!(Some(b"hl2".to_vec()), loader.load("hl2.txt").unwrap());
+    let mut count = 0;
+    loader.for_each_source(|_, _| count += 1);
+    assert_eq!(4, count);
+}
+
+#[test]
+#[cfg(feature = "vpk")]
+fn test_gameinfo_wildcard_vpks() {
+    use crate::Loader;
+    use crate::vpk_entry::build_test_vpk;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let hl2 = dir.path().join("hl2");
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(&hl2).unwrap();
+    build_test_vpk(&hl2, "hl2_misc", 1, &[("misc.txt", b"misc")]);
+    build_test_vpk(&hl2, "HL2_textures", 1, &[("textures.txt", b"textures")]);
+    build_test_vpk(&hl2, "other", 1, &[("other.txt", b"other")]);
+    write(
+        dir.path().join("tf/gameinfo.txt"),
+        r#"
+        "GameInfo"
+        {
+            FileSystem
+            {
+                SearchPaths
+                {
+                    game    |gameinfo_path|.
+                    game    |all_source_engine_paths|hl2/hl2_*.vpk
+                }
+            }
+        }
+        "#,
+    )
+    .unwrap();
+
+    let loader = Loader::from_gameinfo(dir.path()).unwrap();
+    assert!(loader.exists("misc.txt").unwrap());
+    assert!(loader.exists("textures.txt").unwrap());
+    assert!(!loader.exists("other.txt").unwrap());
+    assert_eq!(3, loader.source_count());
+}
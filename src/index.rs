@@ -0,0 +1,206 @@
+use crate::AssetSource;
+#[cfg(test)]
+use crate::LoaderError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::warn;
+
+/// Case-insensitive index of the paths in a source.
+///
+/// Only paths that aren't fully lowercase are indexed, lowercase paths are found by looking up the lowercased path.
+/// The index is built from the source listing on first use, sources that can't be listed only match lowercase paths
+/// case-insensitively. The index is a snapshot of the source, files added to a source after the index is built are
+/// only found by their exact or lowercase path until the index is [cleared](Self::clear).
+///
+/// The index is built once, concurrent lookups wait for the index being built instead of listing the source again.
+#[derive(Default)]
+pub(crate) struct CaseIndex {
+    paths: RwLock<Option<Arc<HashMap<String, String>>>>,
+    /// held while building the index
+    building: Mutex<()>,
+}
+
+impl CaseIndex {
+    /// Get the path as stored in the source for a lowercase path
+    pub fn get(&self, source: &dyn AssetSource, lower: &str) -> Option<String> {
+        if let Some(paths) = self.paths() {
+            return paths.get(lower).cloned();
+        }
+        let _building = self.building.lock().unwrap();
+        if let Some(paths) = self.paths() {
+            return paths.get(lower).cloned();
+        }
+        let listed = source.list("").unwrap_or_else(|error| {
+            warn!(source = source.describe(), %error, "failed to list source, only lowercase paths are matched case-insensitively");
+            Vec::new()
+        });
+        let paths: HashMap<String, String> = listed
+            .into_iter()
+            .filter_map(|path| {
+                let lower = path.to_ascii_lowercase();
+                (lower != path).then_some((lower, path))
+            })
+            .collect();
+        let stored = paths.get(lower).cloned();
+        *self.paths.write().unwrap() = Some(Arc::new(paths));
+        stored
+    }
+
+    /// Drop the index, it's built again from the source on the next lookup
+    pub fn clear(&self) {
+        *self.paths.write().unwrap() = None;
+    }
+
+    fn paths(&self) -> Option<Arc<HashMap<String, String>>> {
+        self.paths.read().unwrap().clone()
+    }
+}
+
+#[test]
+fn test_index_built_once() {
+    use crate::Loader;
+    use crate::source::MemorySource;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::{scope, sleep};
+    use std::time::Duration;
+
+    /// Source that counts how often it's listed
+    struct Counting {
+        inner: MemorySource,
+        lists: Arc<AtomicUsize>,
+    }
+
+    impl AssetSource for Counting {
+        fn has(&self, path: &str) -> Result<bool, LoaderError> {
+            self.inner.has(path)
+        }
+
+        fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            self.inner.load(path)
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+            self.lists.fetch_add(1, Ordering::SeqCst);
+            sleep(Duration::from_millis(20));
+            self.inner.list(prefix)
+        }
+    }
+
+    let lists = Arc::new(AtomicUsize::new(0));
+    let mut loader = Loader::empty();
+    loader.add_source(Counting {
+        inner: MemorySource::from_iter([("materials/Mixed.vmt", "mixed")]),
+        lists: lists.clone(),
+    });
+    scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                assert_eq!(
+                    Some(b"mixed".to_vec()),
+                    loader.load("materials/mixed.vmt").unwrap()
+                );
+            });
+        }
+    });
+    assert_eq!(1, lists.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_dir_mixed_case() {
+    use crate::{Loader, MatchKind};
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/Models/Props")).unwrap();
+    write(dir.path().join("materials/Models/Props/Thing.vmt"), "thing").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    for name in [
+        "materials/Models/Props/Thing.vmt",
+        "materials/models/props/thing.vmt",
+        "MATERIALS/models/PROPS/Thing.VMT",
+    ] {
+        assert_eq!(Some(b"thing".to_vec()), loader.load(name).unwrap());
+    }
+    assert_eq!(
+        Some(MatchKind::CaseInsensitive),
+        loader
+            .exists_detailed("materials/models/props/thing.vmt")
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_unlistable_source() {
+    use crate::Loader;
+    use crate::source::MemorySource;
+
+    /// Source that fails to list its content
+    struct Unlistable(MemorySource);
+
+    impl AssetSource for Unlistable {
+        fn has(&self, path: &str) -> Result<bool, LoaderError> {
+            self.0.has(path)
+        }
+
+        fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            self.0.load(path)
+        }
+
+        fn list(&self, _prefix: &str) -> Result<Vec<String>, LoaderError> {
+            Err(LoaderError::Other("can't list".into()))
+        }
+    }
+
+    let mut loader = Loader::empty();
+    loader.add_source(Unlistable(MemorySource::from_iter([
+        ("materials/lower.vmt", "lower"),
+        ("materials/Mixed.vmt", "mixed"),
+    ])));
+    assert_eq!(
+        Some(b"lower".to_vec()),
+        loader.load("materials/LOWER.vmt").unwrap()
+    );
+    assert_eq!(None, loader.load("materials/mixed.vmt").unwrap());
+    assert!(!loader.exists("materials/missing.vmt").unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_unreadable_subdirectory() {
+    use crate::Loader;
+    use std::fs::{Permissions, create_dir_all, set_permissions, write};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/Models")).unwrap();
+    create_dir_all(dir.path().join("locked")).unwrap();
+    write(dir.path().join("materials/Models/Thing.vmt"), "thing").unwrap();
+    set_permissions(dir.path().join("locked"), Permissions::from_mode(0o000)).unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    let result = loader.load("materials/models/thing.vmt");
+    set_permissions(dir.path().join("locked"), Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(Some(b"thing".to_vec()), result.unwrap());
+}
+
+#[test]
+fn test_clear_index() {
+    use crate::Loader;
+    use std::fs::write;
+
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path().join("First.vmt"), "first").unwrap();
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    assert!(loader.exists("first.vmt").unwrap());
+
+    // files added after the index is built are found once the index is cleared
+    write(dir.path().join("Second.vmt"), "second").unwrap();
+    assert!(!loader.exists("second.vmt").unwrap());
+    loader.clear_cache();
+    assert!(loader.exists("second.vmt").unwrap());
+}
@@ -0,0 +1,146 @@
+//! Permissive parser for the KeyValues text format used by materials, scripts and `gameinfo.txt`.
+//!
+//! The parser never fails, malformed input results in a best-effort tree.
+
+/// A value in a KeyValues tree
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Block(Vec<(String, Value)>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    String(&'a str),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start_matches('\u{feff}');
+    loop {
+        rest = rest.trim_start();
+        let Some(first) = rest.chars().next() else {
+            break;
+        };
+        match first {
+            '{' => {
+                tokens.push(Token::Open);
+                rest = &rest[1..];
+            }
+            '}' => {
+                tokens.push(Token::Close);
+                rest = &rest[1..];
+            }
+            '/' if rest.starts_with("//") => {
+                rest = rest.find('\n').map_or("", |end| &rest[end..]);
+            }
+            // platform conditionals like `[$WIN32]` are ignored
+            '[' => {
+                rest = rest.find(']').map_or("", |end| &rest[end + 1..]);
+            }
+            '"' => {
+                let end = rest[1..].find('"').map_or(rest.len(), |end| end + 1);
+                tokens.push(Token::String(&rest[1..end]));
+                rest = rest.get(end + 1..).unwrap_or("");
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || matches!(c, '{' | '}' | '"'))
+                    .unwrap_or(rest.len());
+                tokens.push(Token::String(&rest[..end]));
+                rest = &rest[end..];
+            }
+        }
+    }
+    tokens
+}
+
+/// Parse KeyValues text into the list of top-level entries
+pub(crate) fn parse(text: &str) -> Vec<(String, Value)> {
+    let tokens = tokenize(text);
+    let mut tokens = tokens.into_iter().peekable();
+    let mut stack: Vec<(String, Vec<(String, Value)>)> = vec![(String::new(), Vec::new())];
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::String(key) => match tokens.peek() {
+                Some(Token::String(value)) => {
+                    let value = Value::String(value.to_string());
+                    stack.last_mut().unwrap().1.push((key.to_string(), value));
+                    tokens.next();
+                }
+                Some(Token::Open) => {
+                    tokens.next();
+                    stack.push((key.to_string(), Vec::new()));
+                }
+                _ => {}
+            },
+            Token::Open => stack.push((String::new(), Vec::new())),
+            Token::Close => {
+                if stack.len() > 1 {
+                    let (key, entries) = stack.pop().unwrap();
+                    stack
+                        .last_mut()
+                        .unwrap()
+                        .1
+                        .push((key, Value::Block(entries)));
+                }
+            }
+        }
+    }
+
+    // close any unterminated blocks
+    while stack.len() > 1 {
+        let (key, entries) = stack.pop().unwrap();
+        stack
+            .last_mut()
+            .unwrap()
+            .1
+            .push((key, Value::Block(entries)));
+    }
+    stack.pop().unwrap().1
+}
+
+/// Iterate over all key-value string pairs in the tree, at any depth
+pub(crate) fn pairs(entries: &[(String, Value)]) -> Vec<(&str, &str)> {
+    let mut found = Vec::new();
+    for (key, value) in entries {
+        match value {
+            Value::String(value) => found.push((key.as_str(), value.as_str())),
+            Value::Block(entries) => found.extend(pairs(entries)),
+        }
+    }
+    found
+}
+
+#[test]
+fn test_parse_kv() {
+    let parsed = parse(
+        r#"
+        "VertexLitGeneric"
+        {
+            // comment
+            "$basetexture" "models/foo/bar"
+            $bumpmap models/foo/bar_normal [$WIN32]
+            "Proxies"
+            {
+                "Sine" { "resultvar" "$alpha" }
+            }
+        }
+        "#,
+    );
+    assert_eq!(1, parsed.len());
+    let (shader, material) = &parsed[0];
+    assert_eq!("VertexLitGeneric", shader);
+    assert!(matches!(material, Value::Block(entries) if entries.len() == 3));
+    assert_eq!(
+        vec![
+            ("$basetexture", "models/foo/bar"),
+            ("$bumpmap", "models/foo/bar_normal"),
+            ("resultvar", "$alpha"),
+        ],
+        pairs(&parsed)
+    );
+}
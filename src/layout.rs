@@ -0,0 +1,104 @@
+use crate::{LoaderError, TF2_APP_ID};
+use glob::{MatchOptions, Pattern};
+
+/// Layouts of the games known to [`GameLayout::for_app`]: app id, game directory and base directories
+const KNOWN_GAMES: &[(u32, &str, &[&str])] = &[
+    (TF2_APP_ID, "tf", &["hl2"]),
+    (220, "hl2", &[]),
+    (240, "cstrike", &["hl2"]),
+    (300, "dod", &["hl2"]),
+    (320, "hl2mp", &["hl2"]),
+    (4000, "garrysmod", &["sourceengine"]),
+];
+
+/// Directory layout of a Source game inside its install directory.
+///
+/// The game directory is mounted first, followed by the base directories containing the content it builds on, like
+/// `hl2` for most games. See [`LoaderBuilder::layout`](crate::LoaderBuilder::layout).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameLayout {
+    /// Steam app id of the game, used to find the install directory
+    pub app_id: u32,
+    /// Directory containing the game content, like `tf` or `cstrike`
+    pub game_dir: String,
+    /// Directories with shared content mounted after the game directory, in order
+    pub base_dirs: Vec<String>,
+    /// Patterns for the file names of the archives opened in the game and base directories, like `tf2_*_dir.vpk`
+    ///
+    /// Every archive is opened when empty.
+    pub archives: Vec<String>,
+}
+
+impl GameLayout {
+    /// Layout for a game in `game_dir`, building on the `hl2` content
+    pub fn new(app_id: u32, game_dir: &str) -> Self {
+        GameLayout {
+            app_id,
+            game_dir: game_dir.into(),
+            base_dirs: vec![String::from("hl2")],
+            archives: Vec::new(),
+        }
+    }
+
+    /// Layout of a known game by its steam app id
+    ///
+    /// Known are Team Fortress 2, Half-Life 2, Counter-Strike: Source, Day of Defeat: Source, Half-Life 2: Deathmatch
+    /// and Garry's Mod. Returns `None` for other games, those can be described with [`new`](Self::new).
+    pub fn for_app(app_id: u32) -> Option<Self> {
+        let (app_id, game_dir, base_dirs) = KNOWN_GAMES.iter().find(|(id, ..)| *id == app_id)?;
+        Some(GameLayout {
+            app_id: *app_id,
+            game_dir: game_dir.to_string(),
+            base_dirs: base_dirs.iter().map(|dir| dir.to_string()).collect(),
+            archives: Vec::new(),
+        })
+    }
+
+    /// Compile the archive patterns
+    pub(crate) fn archive_patterns(&self) -> Result<Vec<Pattern>, LoaderError> {
+        self.archives
+            .iter()
+            .map(|pattern| Ok(Pattern::new(pattern)?))
+            .collect()
+    }
+}
+
+impl Default for GameLayout {
+    fn default() -> Self {
+        GameLayout::for_app(TF2_APP_ID).unwrap()
+    }
+}
+
+/// Check if an archive file name matches one of the patterns, ignoring case
+pub(crate) fn matches_archive(patterns: &[Pattern], name: &str) -> bool {
+    const OPTIONS: MatchOptions = MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(name, OPTIONS))
+}
+
+#[test]
+fn test_for_app() {
+    assert_eq!(GameLayout::new(440, "tf"), GameLayout::default());
+    assert_eq!(
+        vec!["sourceengine"],
+        GameLayout::for_app(4000).unwrap().base_dirs
+    );
+    assert!(GameLayout::for_app(220).unwrap().base_dirs.is_empty());
+    assert_eq!(None, GameLayout::for_app(1));
+
+    let patterns = GameLayout {
+        archives: vec![String::from("tf2_*_dir.vpk")],
+        ..GameLayout::default()
+    }
+    .archive_patterns()
+    .unwrap();
+    assert!(matches_archive(&patterns, "TF2_misc_dir.vpk"));
+    assert!(!matches_archive(&patterns, "pak01_dir.vpk"));
+    assert!(matches_archive(&[], "pak01_dir.vpk"));
+}
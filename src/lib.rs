@@ -17,20 +17,65 @@
 //! }
 //! ```
 
+#[cfg(feature = "tokio")]
+mod async_load;
+#[cfg(feature = "bsp")]
+mod bsp;
+mod builder;
+mod cache;
+mod deps;
+mod fonts;
+mod gameinfo;
+mod index;
+mod kv;
+mod layout;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod scenes;
 pub mod source;
+mod suggest;
+mod trace;
+#[cfg(feature = "vpk")]
+mod vpk_entry;
+mod workshop;
 
-use path_dedot::ParseDot;
-pub use source::AssetSource;
+use crate::cache::{AssetCache, Lookup, PathCache};
+use crate::index::CaseIndex;
+#[cfg(feature = "tokio")]
+pub use async_load::AsyncAssetSource;
+#[cfg(feature = "bsp")]
+pub use bsp::LazyPackfile;
+pub use builder::{ArchiveFactory, LoaderBuilder};
+pub use deps::DependencyGraph;
+use glob::{MatchOptions, Pattern};
+pub use layout::GameLayout;
+#[cfg(feature = "mmap")]
+pub use mmap::MappedBytes;
+pub use scenes::CaptionHeader;
+use source::crc32;
+pub use source::{AssetMetadata, AssetSource};
 use std::borrow::Cow;
-use std::env::var_os;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashSet};
+use std::env::{split_paths, var_os};
+use std::ffi::OsString;
 use std::fmt::{Debug, Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Write, copy};
+use std::ops::{Deref, Range};
+use std::panic::catch_unwind;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use steamlocate::SteamDir;
 use thiserror::Error;
-use tracing::warn;
+use tracing::{debug, warn};
 #[cfg(feature = "bsp")]
 use vbsp::BspError;
+#[cfg(feature = "vpk")]
+pub use vpk_entry::{VpkEntryInfo, VpkExtensionSize, VpkSizeReport};
 
 #[derive(Debug, Error)]
 pub enum LoaderError {
@@ -38,9 +83,26 @@ pub enum LoaderError {
     Tf2NotFound,
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Pattern(#[from] glob::PatternError),
     #[cfg(feature = "zip")]
     #[error(transparent)]
     Zip(#[from] zip::result::ZipError),
+    #[error("Failed to open archive {}", path.display())]
+    Archive {
+        path: PathBuf,
+        #[source]
+        error: Box<LoaderError>,
+    },
+    #[cfg(feature = "vpk")]
+    #[error("Failed to read {path} from vpk archive {} (chunk {archive_index})", archive.display())]
+    VpkEntry {
+        path: String,
+        archive: PathBuf,
+        archive_index: u16,
+        #[source]
+        error: std::io::Error,
+    },
     #[error("{0}")]
     Other(String),
 }
@@ -56,179 +118,2569 @@ impl From<BspError> for LoaderError {
     }
 }
 
+/// How paths are cased when enumerating the loader's contents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NormalizeCase {
+    /// Yield paths in the case they are stored in by each source
+    #[default]
+    Preserve,
+    /// Yield all paths lowercased
+    ///
+    /// This gives a single canonical key per file, even if sources store it with different casing.
+    /// Note that this merges files that only differ in case, which is rare in Source content.
+    Lower,
+}
+
+/// How a path was matched against the loader's sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The path matched a file with the exact same case
+    Exact,
+    /// The path only matched a file with a different case
+    CaseInsensitive,
+}
+
+/// Timing information for loading a single asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadTiming {
+    /// Time spent finding the source that contains the asset
+    pub search: Duration,
+    /// Time spent reading and decompressing the asset from its source
+    pub io: Duration,
+    /// Index of the source the asset was loaded from
+    pub source: usize,
+    /// How the path was matched
+    pub kind: MatchKind,
+}
+
+/// Identifies the source an asset was loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceId {
+    index: usize,
+    description: String,
+}
+
+impl SourceId {
+    /// Index of the source in the search order
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Description of the source, see [`AssetSource::describe`]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for SourceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+/// Path cleaned with [`clean_path`], shared with the [path cache](Loader::with_path_cache) if it's enabled
+enum CleanName<'a> {
+    Cleaned(Cow<'a, str>),
+    Cached(Arc<str>),
+}
+
+impl Deref for CleanName<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            CleanName::Cleaned(name) => name,
+            CleanName::Cached(name) => name,
+        }
+    }
+}
+
+/// Result of querying the sources for a path
+struct Found<T> {
+    value: T,
+    /// index of the source the path was found in
+    source: usize,
+    /// path as it was found in the source
+    path: String,
+    kind: MatchKind,
+}
+
+/// Handle to a source mounted in a loader, can be used to remove the source again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceHandle(u64);
+
+/// Priority group of a mounted source, groups are searched in order of their priority.
+///
+/// Within a group, sources are searched in the order they were added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Searched before all other sources, like the packfile embedded in a map
+    Override,
+    /// Searched together with the sources from the tf2 directory, but after them
+    Game,
+    /// Searched after all other sources, the default for [`Loader::add_source`]
+    Base,
+}
+
+#[derive(Clone)]
+struct Mount {
+    handle: SourceHandle,
+    source: Arc<dyn AssetSource + Send + Sync>,
+    /// description of the source at the time it was mounted
+    description: String,
+    label: Option<String>,
+    /// mounted from the tf2 directory, replaced on refresh
+    auto: bool,
+    /// the mounts from the tf2 directory are in the [`Priority::Game`] group
+    priority: Priority,
+    /// case-insensitive index of the source paths
+    index: Arc<CaseIndex>,
+}
+
 /// The tf2 asset loader instance
 #[derive(Clone)]
 pub struct Loader {
-    sources: Vec<Arc<dyn AssetSource + Send + Sync>>,
+    sources: Vec<Mount>,
+    next_handle: u64,
+    cache: Option<Arc<AssetCache>>,
+    path_cache: Option<Arc<PathCache>>,
+    auto_prefix: bool,
+    builder: Option<LoaderBuilder>,
+    trace: Arc<Mutex<Option<BufWriter<File>>>>,
 }
 
 impl Debug for Loader {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Loader")
-            .field("sources", &self.sources.len())
+            .field("tf2_dir", &self.tf2_dir())
+            .field("sources", &self.sources().collect::<Vec<_>>())
+            .field("cached", &self.cache.is_some())
             .finish_non_exhaustive()
     }
 }
 
 impl Loader {
     /// Create the loader, either auto-detecting the tf2 directory or from the `TF_DIR` environment variable.
+    ///
+    /// If the steam installation can't be detected, `LoaderError::Tf2NotFound` is returned and the `TF_DIR`
+    /// environment variable can be used to specify the tf2 directory instead. Multiple tf2 directories can be
+    /// set with the `TF_DIRS` environment variable, separated like `PATH`, which are searched in order.
     pub fn new() -> Result<Self, LoaderError> {
-        let tf2_dir = tf2_path()?;
-        Self::with_tf2_dir(tf2_dir)
+        Self::for_app(TF2_APP_ID, "tf")
+    }
+
+    /// Create the loader for a different Source game by its steam app id, auto-detecting its install directory.
+    ///
+    /// Assets are loaded from `game_dir` and `hl2` inside the install directory, e.g. `for_app(240, "cstrike")`
+    /// for Counter-Strike: Source. The `TF_DIR` environment variable is only used for tf2 itself.
+    pub fn for_app(app_id: u32, game_dir: &str) -> Result<Self, LoaderError> {
+        LoaderBuilder::new().app(app_id, game_dir).build()
+    }
+
+    /// Create the loader for a game with a different directory layout, auto-detecting its install directory.
+    ///
+    /// See [`GameLayout::for_app`] for the layouts of known games, e.g.
+    /// `Loader::for_game(GameLayout::for_app(4000).unwrap())` for Garry's Mod.
+    pub fn for_game(layout: GameLayout) -> Result<Self, LoaderError> {
+        LoaderBuilder::new().layout(layout).build()
+    }
+
+    /// Create a builder to customize how the loader is constructed.
+    pub fn builder() -> LoaderBuilder {
+        LoaderBuilder::new()
+    }
+
+    /// Create a loader without any sources.
+    ///
+    /// Sources can be mounted with [`add_source`](Self::add_source) and the other mount methods, to only load
+    /// from exactly the sources that are needed. The loader isn't tied to a tf2 directory, so
+    /// [`refresh`](Self::refresh) only clears the caches.
+    pub fn empty() -> Self {
+        Loader {
+            sources: Vec::new(),
+            next_handle: 0,
+            cache: None,
+            path_cache: None,
+            auto_prefix: false,
+            builder: None,
+            trace: Arc::default(),
+        }
     }
 
     /// Create the loader with the specified tf2 directory.
+    ///
+    /// Returns `LoaderError::Tf2NotFound` if the directory doesn't contain the `tf` and `hl2` directories.
     pub fn with_tf2_dir<P: AsRef<Path>>(tf2_dir: P) -> Result<Self, LoaderError> {
-        let tf2_dir = tf2_dir.as_ref();
-
-        let tf_dir = tf2_dir.join("tf");
-        let hl_dir = tf2_dir.join("hl2");
-        let download = tf_dir.join("download");
-
-        #[cfg(feature = "vpk")]
-        let vpks = tf_dir
-            .read_dir()?
-            .chain(hl_dir.read_dir()?)
-            .filter_map(|item| item.ok())
-            .filter_map(|item| Some(item.path().to_str()?.to_string()))
-            .filter(|path| path.ends_with("dir.vpk"))
-            .map(|path| vpk::from_path(&path))
-            .filter_map(|res| {
-                if let Err(e) = &res {
-                    warn!(error = ?e, "error while loading vpk");
-                }
-                res.ok()
-            })
-            .map(|vpk| Arc::new(vpk) as Arc<dyn AssetSource + Send + Sync>);
+        LoaderBuilder::new().tf2_dir(tf2_dir).build()
+    }
+
+    /// Create the loader with the specified tf2 directory, failing if any discovered archive can't be opened.
+    ///
+    /// Unlike [`with_tf2_dir`](Self::with_tf2_dir), which skips corrupt vpk files with a warning, this returns
+    /// [`LoaderError::Archive`] for the first archive that fails to open.
+    pub fn with_tf2_dir_strict<P: AsRef<Path>>(tf2_dir: P) -> Result<Self, LoaderError> {
+        LoaderBuilder::new()
+            .tf2_dir(tf2_dir)
+            .strict_archives(true)
+            .build()
+    }
+
+    /// Create the loader with the specified tf2 directory, mounting the search paths declared in `tf/gameinfo.txt`.
+    ///
+    /// Falls back to the default search paths when `gameinfo.txt` doesn't exist,
+    /// see [`LoaderBuilder::use_gameinfo`] for details.
+    pub fn from_gameinfo<P: AsRef<Path>>(tf2_dir: P) -> Result<Self, LoaderError> {
+        LoaderBuilder::new()
+            .tf2_dir(tf2_dir)
+            .use_gameinfo(true)
+            .build()
+    }
+
+    /// Create the loader with the specified tf2 directory, giving up on discovering vpk files after `timeout`.
+    ///
+    /// When discovery takes longer than the timeout, a warning is logged and only the plain directories are mounted.
+    /// This prevents hanging on slow network mounted installs.
+    pub fn with_tf2_dir_timeout<P: AsRef<Path>>(
+        tf2_dir: P,
+        timeout: Duration,
+    ) -> Result<Self, LoaderError> {
+        LoaderBuilder::new()
+            .tf2_dir(tf2_dir)
+            .scan_timeout(timeout)
+            .build()
+    }
+
+    /// Add a new source to the loader, searched after all existing sources.
+    ///
+    /// This is intended to be used to add data from bsp files, the returned handle can be used
+    /// to [remove](Self::remove_source) the source again. Use [`mount_bsp`](Self::mount_bsp) to mount the packfile
+    /// of a bsp file directly.
+    pub fn add_source<S: AssetSource + Send + Sync + 'static>(
+        &mut self,
+        source: S,
+    ) -> SourceHandle {
+        self.push_source(Arc::new(source), None)
+    }
+
+    /// Add a new source to the loader, searched before all existing sources.
+    ///
+    /// This allows the packfile embedded in a map to override the base game files. The source is added to the
+    /// [`Priority::Override`] group and stays in front of the sources from the tf2 directory when the loader
+    /// is [refreshed](Self::refresh).
+    pub fn insert_source_front<S: AssetSource + Send + Sync + 'static>(
+        &mut self,
+        source: S,
+    ) -> SourceHandle {
+        self.insert_source(Arc::new(source), Priority::Override, 0)
+    }
+
+    /// Add a new source to the loader in a priority group, searched after the existing sources of the group.
+    ///
+    /// For example mod files added as [`Priority::Override`] are searched before the sources from the tf2
+    /// directory, while sources added as [`Priority::Game`] are searched after the tf2 directory but before
+    /// any sources added with [`add_source`](Self::add_source).
+    pub fn add_source_with_priority<S: AssetSource + Send + Sync + 'static>(
+        &mut self,
+        source: S,
+        priority: Priority,
+    ) -> SourceHandle {
+        self.push_with_priority(Arc::new(source), priority)
+    }
+
+    pub(crate) fn push_with_priority(
+        &mut self,
+        source: Arc<dyn AssetSource + Send + Sync>,
+        priority: Priority,
+    ) -> SourceHandle {
+        let index = self
+            .sources
+            .iter()
+            .position(|mount| mount.priority > priority)
+            .unwrap_or(self.sources.len());
+        self.insert_source(source, priority, index)
+    }
+
+    /// Add a new source to the loader at a position in the search order, with `0` being searched first.
+    ///
+    /// The source joins the [priority group](Priority) of the source currently at `index`, or of the last source if
+    /// `index` is past the end. Sources inserted in between the sources from the tf2 directory are moved after them
+    /// when the loader is [refreshed](Self::refresh).
+    pub fn insert_source_at<S: AssetSource + Send + Sync + 'static>(
+        &mut self,
+        index: usize,
+        source: S,
+    ) -> SourceHandle {
+        let index = index.min(self.sources.len());
+        let priority = self
+            .sources
+            .get(index)
+            .or(self.sources.last())
+            .map_or(Priority::Base, |mount| mount.priority);
+        self.insert_source(Arc::new(source), priority, index)
+    }
+
+    /// Move a mounted source to another priority group, searched after the existing sources of the group.
+    ///
+    /// Sources from the tf2 directory are mounted again with [`Priority::Game`] when the loader is
+    /// [refreshed](Self::refresh). Returns `false` if the source was already removed.
+    pub fn set_priority(&mut self, handle: SourceHandle, priority: Priority) -> bool {
+        let Some(position) = self.sources.iter().position(|mount| mount.handle == handle) else {
+            return false;
+        };
+        let mut mount = self.sources.remove(position);
+        mount.priority = priority;
+        let index = self
+            .sources
+            .iter()
+            .position(|mount| mount.priority > priority)
+            .unwrap_or(self.sources.len());
+        self.sources.insert(index, mount);
+        self.sources_changed();
+        true
+    }
+
+    fn insert_source(
+        &mut self,
+        source: Arc<dyn AssetSource + Send + Sync>,
+        priority: Priority,
+        index: usize,
+    ) -> SourceHandle {
+        let handle = self.push_source(source, None);
+        let mut mount = self.sources.pop().unwrap();
+        mount.priority = priority;
+        self.sources.insert(index, mount);
+        handle
+    }
+
+    /// Add a new source to the loader with a label, to restrict lookups to it with [`load_from_labels`](Self::load_from_labels).
+    ///
+    /// Sources mounted from the tf2 directory are labeled with their path relative to the tf2 directory,
+    /// like `tf`, `hl2` or `tf/tf2_misc_dir.vpk`.
+    pub fn add_labeled_source<S: AssetSource + Send + Sync + 'static>(
+        &mut self,
+        label: &str,
+        source: S,
+    ) -> SourceHandle {
+        self.push_source(Arc::new(source), Some(label.into()))
+    }
+
+    fn push_source(
+        &mut self,
+        source: Arc<dyn AssetSource + Send + Sync>,
+        label: Option<String>,
+    ) -> SourceHandle {
+        let handle = SourceHandle(self.next_handle);
+        self.next_handle += 1;
+        self.sources.push(Mount {
+            handle,
+            description: source.describe(),
+            source,
+            label,
+            auto: false,
+            priority: Priority::Base,
+            index: Arc::default(),
+        });
+        self.sources_changed();
+        handle
+    }
+
+    /// Start with an empty cache after the sources changed, cached data might come from a different source now
+    ///
+    /// This also stops sharing the cache with clones of the loader, which keep their sources.
+    fn sources_changed(&mut self) {
+        if let Some(cache) = &self.cache {
+            self.cache = Some(Arc::new(AssetCache::new(cache.capacity())));
+        }
+    }
+
+    /// Add a set of layered directories as sources, like an overlay filesystem.
+    ///
+    /// Later layers take precedence over earlier ones, so with `[base, patch]` a file present in both directories
+    /// is loaded from `patch`, while files only present in `base` are still found.
+    /// As with [`add_source`](Self::add_source), the layers are searched after all previously added sources.
+    pub fn add_layered_dirs(&mut self, layers: &[PathBuf]) {
+        for layer in layers.iter().rev() {
+            self.push_source(Arc::new(layer.clone()), None);
+        }
+    }
+
+    /// Remove a previously mounted source from the loader.
+    ///
+    /// Returns `false` if the source was already removed.
+    pub fn remove_source(&mut self, handle: SourceHandle) -> bool {
+        let count = self.sources.len();
+        self.sources.retain(|mount| mount.handle != handle);
+        let removed = self.sources.len() != count;
+        if removed {
+            self.sources_changed();
+        }
+        removed
+    }
+
+    /// Get the indices of the sources that the given assets are loaded from, sorted by index.
+    ///
+    /// Only the source that wins resolution for an asset is included, assets that aren't found are ignored.
+    pub fn sources_needed_for(&self, names: &[&str]) -> Result<Vec<usize>, LoaderError> {
+        let mut needed = BTreeSet::new();
+        for name in names {
+            let found = self.find(name, |source, path| Ok(source.has(path)?.then_some(())))?;
+            needed.extend(found.map(|found| found.source));
+        }
+        Ok(needed.into_iter().collect())
+    }
+
+    /// Iterate over the descriptions of all sources, in search order.
+    ///
+    /// The descriptions are like `dir:/path/to/tf` or `vpk:tf2_misc_dir.vpk`, see [`AssetSource::describe`].
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().map(|mount| mount.description.as_str())
+    }
+
+    /// Iterate over the handles and descriptions of all sources, in search order.
+    ///
+    /// The handles can be used to [remove](Self::remove_source) or [reprioritize](Self::set_priority) any source,
+    /// including the sources mounted from the tf2 directory.
+    pub fn source_handles(&self) -> impl Iterator<Item = (SourceHandle, &str)> {
+        self.sources
+            .iter()
+            .map(|mount| (mount.handle, mount.description.as_str()))
+    }
+
+    /// Check if a source with the description is mounted, see [`sources`](Self::sources).
+    ///
+    /// This can be used to avoid mounting the same map packfile or vpk multiple times.
+    pub fn is_mounted(&self, description: &str) -> bool {
+        self.sources().any(|source| source == description)
+    }
 
-        #[allow(unused_mut)]
-        let mut sources = vec![
-            Arc::new(tf_dir) as Arc<dyn AssetSource + Send + Sync>,
-            Arc::new(hl_dir),
-        ];
+    /// The number of mounted sources.
+    pub fn source_count(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// The tf2 directory the sources were discovered from, either auto-detected or as configured.
+    ///
+    /// Returns `None` for loaders that weren't discovered from a tf2 directory, like [`LoaderBuilder::mount_defaults`]
+    /// loaders without a tf2 directory.
+    pub fn tf2_dir(&self) -> Option<&Path> {
+        self.builder.as_ref()?.resolved_tf2_dir()
+    }
 
-        if download.exists() {
-            sources.push(Arc::new(download));
+    /// Call `f` with every source and its index, in search order.
+    ///
+    /// The sources are only borrowed for the duration of each call, so the loader can't be modified from within `f`.
+    pub fn for_each_source<F: FnMut(usize, &dyn AssetSource)>(&self, mut f: F) {
+        for (index, mount) in self.sources.iter().enumerate() {
+            f(index, mount.source.as_ref());
         }
+    }
 
-        #[cfg(feature = "vpk")]
-        sources.extend(vpks);
+    /// Add the packfile embedded in a bsp file as a source, without keeping the bsp in memory.
+    ///
+    /// Only the directory of the packfile is read up front, the embedded files are read from the bsp file when loaded.
+    /// Like with [`mount_bsp`](Self::mount_bsp), the packfile is searched before all existing sources.
+    #[cfg(feature = "bsp")]
+    pub fn add_bsp_lazy<P: AsRef<Path>>(
+        &mut self,
+        bsp_path: P,
+    ) -> Result<SourceHandle, LoaderError> {
+        let packfile = LazyPackfile::open(bsp_path)?;
+        Ok(self.insert_source_front(packfile))
+    }
 
-        Ok(Loader { sources })
+    /// Add the zip archive at the specified path as a source, searched after all existing sources.
+    ///
+    /// Only the directory of the archive is read up front, the files are read from the archive when loaded.
+    /// Uncompressed files are streamed from the archive when [opened](Self::open).
+    #[cfg(feature = "zip")]
+    pub fn mount_zip<P: AsRef<Path>>(&mut self, path: P) -> Result<SourceHandle, LoaderError> {
+        let zip = source::zip::ZipFileSource::open(path.as_ref())?;
+        Ok(self.add_source(zip))
     }
 
-    /// Add a new source to the loader.
+    /// Add a zip archive read from `reader` as a source, searched after all existing sources.
     ///
-    /// This is intended to be used to add data from bsp files
-    pub fn add_source<S: AssetSource + Send + Sync + 'static>(&mut self, source: S) {
-        self.sources.push(Arc::new(source))
+    /// This can be used to mount in-memory archives by wrapping the data in a [`Cursor`].
+    #[cfg(feature = "zip")]
+    pub fn mount_zip_reader<R: Read + std::io::Seek + Send + 'static>(
+        &mut self,
+        reader: R,
+    ) -> Result<SourceHandle, LoaderError> {
+        let zip = zip::ZipArchive::new(reader)?;
+        Ok(self.push_source(Arc::new(Mutex::new(zip)), None))
     }
 
     /// Check if a file by path exists.
     #[tracing::instrument(skip(self))]
     pub fn exists(&self, name: &str) -> Result<bool, LoaderError> {
-        let name = clean_path(name);
-        for source in self.sources.iter() {
-            if source.has(&name)? {
-                return Ok(true);
+        Ok(self.exists_detailed(name)?.is_some())
+    }
+
+    /// Check if a file by path exists, and whether it was matched exactly or only case-insensitively.
+    ///
+    /// Files that only match case-insensitively will work for the windows client but can fail on case-sensitive
+    /// systems such as linux servers.
+    #[tracing::instrument(skip(self))]
+    pub fn exists_detailed(&self, name: &str) -> Result<Option<MatchKind>, LoaderError> {
+        let found = self.find(name, |source, path| Ok(source.has(path)?.then_some(())))?;
+        Ok(found.map(|found| found.kind))
+    }
+
+    /// Load a file by path.
+    ///
+    /// Returns the file data as `Vec<u8>` or `None` if the path doesn't exist.
+    #[tracing::instrument(skip(self))]
+    pub fn load(&self, name: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        let Some(cache) = &self.cache else {
+            let found = self.find(name, |source, path| source.load(path))?;
+            return Ok(found.map(|found| {
+                self.record_trace(&found);
+                debug!(bytes = found.value.len(), "loaded asset");
+                found.value
+            }));
+        };
+
+        let key = self.clean_name(name);
+        let found = cache.get_or_load(
+            &key,
+            || self.find(&key, |source, path| source.load(path)),
+            |found| Arc::from(found.value.as_slice()),
+        )?;
+        match found {
+            Some(Lookup::Cached(data)) => {
+                self.trace_cache_hit(&key)?;
+                debug!(bytes = data.len(), "loaded asset from cache");
+                Ok(Some(data.to_vec()))
             }
+            Some(Lookup::Loaded(found)) => {
+                self.record_trace(&found);
+                debug!(bytes = found.value.len(), "loaded asset");
+                Ok(Some(found.value))
+            }
+            None => Ok(None),
         }
+    }
 
-        let lower_name = name.to_ascii_lowercase();
-        if name != lower_name {
-            for source in self.sources.iter() {
-                if source.has(&lower_name)? {
-                    return Ok(true);
-                }
+    /// Load a file by path as a shared buffer.
+    ///
+    /// With a [cache](Self::with_cache), cache hits return the cached data without copying it.
+    /// Returns `None` if the path doesn't exist.
+    #[tracing::instrument(skip(self))]
+    pub fn load_shared(&self, name: &str) -> Result<Option<Arc<[u8]>>, LoaderError> {
+        let Some(cache) = &self.cache else {
+            let found = self.find(name, |source, path| source.load_shared(path))?;
+            return Ok(found.map(|found| {
+                self.record_trace(&found);
+                found.value
+            }));
+        };
+
+        let key = self.clean_name(name);
+        let found = cache.get_or_load(
+            &key,
+            || self.find(&key, |source, path| source.load_shared(path)),
+            |found| found.value.clone(),
+        )?;
+        match found {
+            Some(Lookup::Cached(data)) => {
+                self.trace_cache_hit(&key)?;
+                Ok(Some(data))
             }
+            Some(Lookup::Loaded(found)) => {
+                self.record_trace(&found);
+                Ok(Some(found.value))
+            }
+            None => Ok(None),
         }
+    }
 
-        Ok(false)
+    fn trace_cache_hit(&self, key: &str) -> Result<(), LoaderError> {
+        if self.is_tracing() {
+            // the cache doesn't know where the data came from
+            let found = self.find(key, |source, path| Ok(source.has(path)?.then_some(())))?;
+            if let Some(found) = found {
+                self.record_trace(&found);
+            }
+        }
+        Ok(())
     }
 
-    /// Load a file by path.
+    /// Load part of a file by path, like the header of a texture.
     ///
-    /// Returns the file data as `Vec<u8>` or `None` if the path doesn't exist.
+    /// Ranges extending past the end of the file are truncated to the end of the file,
+    /// sources that support it only read the requested part of the file.
+    /// Returns `None` if the path doesn't exist.
     #[tracing::instrument(skip(self))]
-    pub fn load(&self, name: &str) -> Result<Option<Vec<u8>>, LoaderError> {
-        let name = clean_path(name);
-        for source in self.sources.iter() {
-            if let Some(data) = source.load(&name)? {
-                return Ok(Some(data));
+    pub fn load_range(
+        &self,
+        name: &str,
+        range: Range<u64>,
+    ) -> Result<Option<Vec<u8>>, LoaderError> {
+        if let Some(cache) = &self.cache {
+            let key = self.clean_name(name);
+            if let Some(data) = cache.get(&key) {
+                self.trace_cache_hit(&key)?;
+                let len = data.len() as u64;
+                let range = range.start.min(len) as usize..range.end.min(len) as usize;
+                return Ok(Some(data.get(range).unwrap_or_default().to_vec()));
             }
         }
+        let found = self.find(name, |source, path| source.load_range(path, range.clone()))?;
+        Ok(found.map(|found| {
+            self.record_trace(&found);
+            found.value
+        }))
+    }
 
-        let lower_name = name.to_ascii_lowercase();
-        if name != lower_name {
-            for source in self.sources.iter() {
-                if let Some(data) = source.load(&lower_name)? {
-                    return Ok(Some(data));
-                }
+    /// Get the size of a file by path without loading it.
+    ///
+    /// Returns `None` if the path doesn't exist.
+    #[tracing::instrument(skip(self))]
+    pub fn size(&self, name: &str) -> Result<Option<u64>, LoaderError> {
+        if let Some(cache) = &self.cache {
+            if let Some(data) = cache.get(&self.clean_name(name)) {
+                return Ok(Some(data.len() as u64));
             }
         }
+        let found = self.find(name, |source, path| source.size(path))?;
+        Ok(found.map(|found| found.value))
+    }
 
-        Ok(None)
+    /// Get the metadata of a file by path without loading it, together with the source it was found in.
+    ///
+    /// The modification time is only known for files in directory sources, the compressed size only for files stored
+    /// compressed in zip archives. Returns `None` if the path doesn't exist. This bypasses the cache.
+    #[tracing::instrument(skip(self))]
+    pub fn stat(&self, name: &str) -> Result<Option<(AssetMetadata, SourceId)>, LoaderError> {
+        let found = self.find(name, |source, path| source.metadata(path))?;
+        Ok(found.map(|found| {
+            let source = SourceId {
+                index: found.source,
+                description: self.sources[found.source].description.clone(),
+            };
+            (found.value, source)
+        }))
     }
 
-    /// Look for a file by name in one or more paths
-    pub fn find_in_paths<S: Display>(&self, name: &str, paths: &[S]) -> Option<String> {
-        for path in paths {
-            let full_path = format!("{}{}", path, name);
-            let full_path = clean_path(&full_path);
-            if self.exists(&full_path).unwrap_or_default() {
-                return Some(full_path.to_string());
+    /// Open a reader over a file by path.
+    ///
+    /// Unlike [`load`](Self::load), sources that support it read the file incrementally instead of loading it into
+    /// memory up front, cached files are read from the cache.
+    /// Returns `None` if the path doesn't exist.
+    pub fn open(&self, name: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        if let Some(cache) = &self.cache {
+            let key = self.clean_name(name);
+            if let Some(data) = cache.get(&key) {
+                self.trace_cache_hit(&key)?;
+                return Ok(Some(Box::new(Cursor::new(data))));
             }
         }
+        let found = self.find(name, |source, path| source.open(path))?;
+        Ok(found.map(|found| {
+            self.record_trace(&found);
+            found.value
+        }))
+    }
 
-        let lower_name = name.to_ascii_lowercase();
-        if name != lower_name {
-            for path in paths {
-                let full_path = format!("{}{}", path, lower_name);
-                let full_path = clean_path(&full_path);
-                if self.exists(&full_path).unwrap_or_default() {
-                    return Some(full_path.to_string());
-                }
+    /// Copy a file by path into `out`, without loading it into memory up front.
+    ///
+    /// Returns `false` if the path doesn't exist.
+    pub fn write_to<W: Write>(&self, name: &str, out: &mut W) -> Result<bool, LoaderError> {
+        let Some(mut reader) = self.open(name)? else {
+            return Ok(false);
+        };
+        copy(&mut reader, out)?;
+        Ok(true)
+    }
+
+    /// Get the CRC32 checksum of a file by path, as stored in the vpk or zip file containing it.
+    ///
+    /// For sources that don't store checksums, like directories, the checksum is computed from the file data.
+    /// Returns `None` if the path doesn't exist.
+    pub fn crc(&self, name: &str) -> Result<Option<u32>, LoaderError> {
+        let found = self.find(name, |source, path| source.crc(path))?;
+        Ok(found.map(|found| found.value))
+    }
+
+    /// Get the CRC32 checksum stored for a file by path in the vpk or zip file containing it.
+    ///
+    /// Returns `None` if the path doesn't exist or if the source containing it doesn't store checksums, like
+    /// directories.
+    pub fn stored_crc(&self, name: &str) -> Result<Option<u32>, LoaderError> {
+        let found = self.find(name, |source, path| match source.stored_crc(path)? {
+            Some(crc) => Ok(Some(Some(crc))),
+            None => Ok(source.has(path)?.then_some(None)),
+        })?;
+        Ok(found.and_then(|found| found.value))
+    }
+
+    /// Verify the data of a file by path against the CRC32 checksum stored for it, see [`stored_crc`](Self::stored_crc).
+    ///
+    /// Returns `None` if the path doesn't exist or if the source containing it doesn't store a checksum to verify
+    /// against. This always reads the file from its source, bypassing the cache.
+    pub fn verify(&self, name: &str) -> Result<Option<bool>, LoaderError> {
+        let found = self.find(name, |source, path| {
+            let Some(stored) = source.stored_crc(path)? else {
+                return Ok(source.has(path)?.then_some(None));
+            };
+            Ok(source.load(path)?.map(|data| Some(crc32(&data) == stored)))
+        })?;
+        Ok(found.and_then(|found| found.value))
+    }
+
+    /// Load a file by path together with the 64-bit FNV-1a hash of its data, hashing the data while it is read.
+    ///
+    /// Returns `None` if the path doesn't exist.
+    pub fn load_and_hash(&self, name: &str) -> Result<Option<(Vec<u8>, u64)>, LoaderError> {
+        let Some(mut reader) = self.open(name)? else {
+            return Ok(None);
+        };
+        let mut data = Vec::new();
+        let mut hash = FNV_OFFSET;
+        let mut buffer = [0; 8 * 1024];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for byte in &buffer[..read] {
+                hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
             }
+            data.extend_from_slice(&buffer[..read]);
         }
+        Ok(Some((data, hash)))
+    }
+
+    /// Load a file by path, only looking in the sources with one of the given labels.
+    ///
+    /// Returns `None` if none of the labeled sources contain the path. This bypasses the cache.
+    pub fn load_from_labels(
+        &self,
+        name: &str,
+        labels: &[&str],
+    ) -> Result<Option<Vec<u8>>, LoaderError> {
+        let include = |mount: &Mount| {
+            mount
+                .label
+                .as_deref()
+                .is_some_and(|label| labels.contains(&label))
+        };
+        let found = self.find_in(name, include, |source, path| source.load(path))?;
+        Ok(found.map(|found| {
+            self.record_trace(&found);
+            found.value
+        }))
+    }
 
-        None
+    /// Load a file by path, together with the source it was loaded from.
+    ///
+    /// This always reads the file from its source, bypassing the cache.
+    pub fn load_with_source(&self, name: &str) -> Result<Option<(Vec<u8>, SourceId)>, LoaderError> {
+        let found = self.find(name, |source, path| source.load(path))?;
+        Ok(found.map(|found| {
+            self.record_trace(&found);
+            let source = SourceId {
+                index: found.source,
+                description: self.sources[found.source].description.clone(),
+            };
+            (found.value, source)
+        }))
+    }
+
+    /// Load a file by path, recording how long finding and reading the file took.
+    ///
+    /// The time spent querying sources that don't contain the file counts as search time, the time spent loading
+    /// the file from the source that contains it as io time.
+    /// This always reads the file from its source, bypassing the cache.
+    pub fn load_timed(&self, name: &str) -> Result<Option<(Vec<u8>, LoadTiming)>, LoaderError> {
+        let start = Instant::now();
+        let mut io = Duration::ZERO;
+        let found = self.find(name, |source, path| {
+            let load_start = Instant::now();
+            let data = source.load(path)?;
+            if data.is_some() {
+                io = load_start.elapsed();
+            }
+            Ok(data)
+        })?;
+        let total = start.elapsed();
+        Ok(found.map(|found| {
+            self.record_trace(&found);
+            let timing = LoadTiming {
+                search: total.saturating_sub(io),
+                io,
+                source: found.source,
+                kind: found.kind,
+            };
+            (found.value, timing)
+        }))
     }
-}
 
-fn clean_path(path: &str) -> Cow<str> {
-    if path.contains("/../") {
-        let path_buf = PathBuf::from(format!("/{path}"));
-        let Ok(absolute_path) = path_buf.parse_dot_from("/") else {
-            return path.into();
+    /// Load multiple files by path, returning each requested name with the result of loading it, in the order they
+    /// were requested.
+    ///
+    /// The data is `None` for files that don't exist, failing to load one file doesn't affect the other files.
+    /// With the `rayon` feature the files are loaded in parallel on the global rayon thread pool.
+    #[allow(clippy::type_complexity)]
+    pub fn load_many<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        names: I,
+    ) -> Vec<(String, Result<Option<Vec<u8>>, LoaderError>)> {
+        #[cfg(feature = "rayon")]
+        use rayon::prelude::*;
+
+        let names: Vec<&str> = names.into_iter().collect();
+        #[cfg(feature = "rayon")]
+        let names = names.into_par_iter();
+        #[cfg(not(feature = "rayon"))]
+        let names = names.into_iter();
+        names
+            .map(|name| (name.to_string(), self.load(name)))
+            .collect()
+    }
+
+    /// Enable caching of loaded assets, keeping up to `capacity_bytes` of recently loaded assets in memory.
+    ///
+    /// Once the capacity is exceeded, the least recently used assets are evicted.
+    /// Concurrent loads of the same uncached path only load it once.
+    /// The cache is shared between clones of the loader, until the sources of the loader are changed by adding,
+    /// removing or reprioritizing a source, which starts the loader with a new empty cache.
+    pub fn with_cache(mut self, capacity_bytes: usize) -> Self {
+        self.cache = Some(Arc::new(AssetCache::new(capacity_bytes)));
+        self
+    }
+
+    /// Retry lookups that aren't found with the conventional directory for the file type prepended.
+    ///
+    /// With this enabled, `props_gameplay/foo.mdl` is also looked up as `models/props_gameplay/foo.mdl`.
+    /// The `models/` prefix is used for `.mdl` files, `materials/` for `.vmt` and `.vtf` files and `sound/` for
+    /// `.wav` and `.mp3` files. Disabled by default.
+    pub fn set_auto_prefix(&mut self, auto_prefix: bool) {
+        self.auto_prefix = auto_prefix;
+    }
+
+    /// Remember the normalized form of up to `capacity` requested paths.
+    ///
+    /// This skips normalizing paths again when the same paths are looked up repeatedly.
+    /// The cache is shared between clones of the loader.
+    pub fn with_path_cache(mut self, capacity: usize) -> Self {
+        self.path_cache = Some(Arc::new(PathCache::new(capacity)));
+        self
+    }
+
+    /// Clean a requested path, through the path cache if it's enabled
+    fn clean_name<'a>(&self, name: &'a str) -> CleanName<'a> {
+        match &self.path_cache {
+            Some(path_cache) => CleanName::Cached(
+                path_cache.get_or_insert(name, |name| clean_path(name).into_owned()),
+            ),
+            None => CleanName::Cleaned(clean_path(name)),
+        }
+    }
+
+    /// Re-discover the sources from the tf2 directory and drop all cached data.
+    ///
+    /// This picks up vpk files that were added, removed or changed by a game update without creating a new loader,
+    /// the case-insensitive indexes of all sources are rebuilt as well, see [`clear_cache`](Self::clear_cache).
+    /// Sources added manually are kept in their [priority](Priority) group, the re-discovered sources are searched
+    /// before other sources of the [`Priority::Game`] group.
+    /// If discovery fails the loader is left unchanged, clones of the loader keep using their existing sources and cache.
+    pub fn refresh(&mut self) -> Result<(), LoaderError> {
+        let discovered = match &self.builder {
+            Some(builder) => Some(builder.discover()?),
+            None => None,
         };
-        let path = absolute_path.to_str().unwrap().trim_start_matches('/');
-        String::from(path).into()
-    } else {
-        path.into()
+
+        if let Some(discovered) = discovered {
+            let (front, manual): (Vec<Mount>, Vec<Mount>) = self
+                .sources
+                .drain(..)
+                .filter(|mount| !mount.auto)
+                .partition(|mount| mount.priority == Priority::Override);
+            self.sources.extend(front);
+            let discovered_start = self.sources.len();
+            for (label, source) in discovered {
+                self.push_source(source, Some(label));
+            }
+            for mount in self.sources[discovered_start..].iter_mut() {
+                mount.auto = true;
+                mount.priority = Priority::Game;
+            }
+            self.sources.extend(manual);
+        }
+
+        self.sources_changed();
+        // the case-insensitive indexes are shared with clones of the loader, which keep their snapshot
+        for mount in self.sources.iter_mut() {
+            mount.index = Arc::default();
+        }
+        if let Some(path_cache) = &self.path_cache {
+            self.path_cache = Some(Arc::new(PathCache::new(path_cache.capacity())));
+        }
+        Ok(())
     }
-}
 
-#[test]
-fn test_clean_path() {
-    assert_eq!("foo/bar", clean_path("foo/bar"));
-    assert_eq!("foo/bar", clean_path("foo/asd/../bar"));
-    assert_eq!("../bar", clean_path("../bar"));
-}
+    /// Release unused capacity of the asset and path caches back to the allocator.
+    ///
+    /// Cached assets and paths are kept, use [`clear_cache`](Self::clear_cache) to drop them.
+    /// The directory trees of mounted vpk files and the case-insensitive indexes of the sources are kept
+    /// as well, since they are needed for every lookup.
+    pub fn shrink_to_fit(&self) {
+        if let Some(cache) = &self.cache {
+            cache.shrink_to_fit();
+        }
+        if let Some(path_cache) = &self.path_cache {
+            path_cache.shrink_to_fit();
+        }
+    }
 
-fn tf2_path() -> Result<PathBuf, LoaderError> {
-    if let Some(path) = var_os("TF_DIR") {
-        let path: PathBuf = path.into();
-        if path.is_dir() {
-            Ok(path)
-        } else {
-            Err(LoaderError::Tf2NotFound)
+    /// Remove all assets from the cache and drop the case-insensitive indexes of the sources.
+    ///
+    /// The indexes are a snapshot of the files in each source, built on the first lookup that doesn't match a path
+    /// exactly. Clearing them picks up mixed case files that were added to directory sources since.
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
         }
-    } else {
-        let (app, library) = SteamDir::locate()
-            .map_err(|_| LoaderError::Tf2NotFound)?
-            .find_app(440)
-            .map_err(|_| LoaderError::Tf2NotFound)?
-            .ok_or(LoaderError::Tf2NotFound)?;
-        Ok(library.resolve_app_dir(&app))
+        for mount in self.sources.iter() {
+            mount.index.clear();
+        }
+    }
+
+    /// Remove a single asset from the cache.
+    ///
+    /// This only drops the cache's copy of the asset, returns `false` if the asset wasn't cached.
+    pub fn evict(&self, name: &str) -> bool {
+        match &self.cache {
+            Some(cache) => cache.remove(&self.clean_name(name)),
+            None => false,
+        }
+    }
+
+    /// Remove all assets matching a glob pattern from the cache, returning the number of evicted assets.
+    ///
+    /// `*` and `?` match within a single path segment, `**` matches across directories.
+    pub fn evict_matching(&self, pattern: &str) -> Result<usize, LoaderError> {
+        let pattern = Pattern::new(&clean_path(pattern))?;
+        Ok(match &self.cache {
+            Some(cache) => cache.retain(|path| !pattern.matches_with(path, GLOB_OPTIONS)),
+            None => 0,
+        })
     }
+
+    /// Load a file by path as [`bytes::Bytes`].
+    ///
+    /// Returns `None` if the path doesn't exist.
+    #[cfg(feature = "bytes")]
+    pub fn load_bytes(&self, name: &str) -> Result<Option<bytes::Bytes>, LoaderError> {
+        Ok(self.load(name)?.map(bytes::Bytes::from))
+    }
+
+    /// Load a text file by path, like a material or script.
+    ///
+    /// A leading UTF-8 byte order mark is stripped. Returns `LoaderError::Other` if the file isn't valid UTF-8
+    /// and `None` if the path doesn't exist.
+    pub fn load_string(&self, name: &str) -> Result<Option<String>, LoaderError> {
+        let Some(mut data) = self.load(name)? else {
+            return Ok(None);
+        };
+        if data.starts_with(UTF8_BOM) {
+            data.drain(..UTF8_BOM.len());
+        }
+        String::from_utf8(data)
+            .map(Some)
+            .map_err(|e| LoaderError::Other(format!("{name} is not valid UTF-8: {e}")))
+    }
+
+    /// Load all variants of a file that differ only by suffix, such as `.dx90.vtx`, `.dx80.vtx` and `.sw.vtx` for models.
+    ///
+    /// Returns the path and data for each variant that exists, in the order of the provided suffixes.
+    pub fn load_variants(
+        &self,
+        base: &str,
+        suffixes: &[&str],
+    ) -> Result<Vec<(String, Vec<u8>)>, LoaderError> {
+        let mut variants = Vec::new();
+        for suffix in suffixes {
+            let path = format!("{base}{suffix}");
+            if let Some(data) = self.load(&path)? {
+                variants.push((clean_path(&path).into_owned(), data));
+            }
+        }
+        Ok(variants)
+    }
+
+    /// Resolve a texture name as referenced by materials to a loadable path.
+    ///
+    /// The name can either be a full `materials/...vtf` path or a bare texture name like `models/foo/bar`,
+    /// the `materials/` prefix and `.vtf` extension are added when missing.
+    pub fn resolve_texture(&self, name: &str) -> Result<Option<String>, LoaderError> {
+        let name = name.trim_start_matches('/');
+        let stem = strip_suffix_ignore_case(name, ".vtf").unwrap_or(name);
+        let stem = strip_prefix_ignore_case(stem, "materials/").unwrap_or(stem);
+
+        // some textures are referenced relative to the game root instead of the materials directory
+        for candidate in [format!("materials/{stem}.vtf"), format!("{stem}.vtf")] {
+            if self.exists(&candidate)? {
+                return Ok(Some(clean_path(&candidate).into_owned()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Query the sources in order until `f` returns a result, trying each source with the path as-is and then with
+    /// the path as stored in the source ignoring case.
+    fn find<T, F>(&self, name: &str, f: F) -> Result<Option<Found<T>>, LoaderError>
+    where
+        F: FnMut(&dyn AssetSource, &str) -> Result<Option<T>, LoaderError>,
+    {
+        self.find_in(name, |_| true, f)
+    }
+
+    /// Like [`find`](Self::find), only querying the sources for which `include` returns `true`.
+    fn find_in<T, I, F>(
+        &self,
+        name: &str,
+        include: I,
+        mut f: F,
+    ) -> Result<Option<Found<T>>, LoaderError>
+    where
+        I: Fn(&Mount) -> bool,
+        F: FnMut(&dyn AssetSource, &str) -> Result<Option<T>, LoaderError>,
+    {
+        let _depth = ResolveDepth::enter(name)?;
+        if is_internal_reference(name) {
+            return Ok(None);
+        }
+        let name = self.clean_name(name);
+        let name: &str = &name;
+        let mut found = self.search(name, &include, &mut f)?;
+
+        if found.is_none() && self.auto_prefix {
+            let prefix = AUTO_PREFIXES
+                .iter()
+                .find(|(ext, _)| strip_suffix_ignore_case(name, ext).is_some())
+                .map(|(_, prefix)| *prefix);
+            if let Some(prefix) = prefix {
+                if strip_prefix_ignore_case(name, prefix).is_none() {
+                    found = self.search(&format!("{prefix}{name}"), &include, &mut f)?;
+                }
+            }
+        }
+
+        match &found {
+            Some(found) => debug!(
+                source = found.source,
+                description = %self.sources[found.source].description,
+                path = %found.path,
+                kind = ?found.kind,
+                "found asset"
+            ),
+            None => debug!(
+                sources = self.sources.len(),
+                "asset not found in any source"
+            ),
+        }
+        Ok(found)
+    }
+
+    /// Query the sources for a cleaned path, see [`find`](Self::find).
+    fn search<T, I, F>(
+        &self,
+        name: &str,
+        include: &I,
+        f: &mut F,
+    ) -> Result<Option<Found<T>>, LoaderError>
+    where
+        I: Fn(&Mount) -> bool,
+        F: FnMut(&dyn AssetSource, &str) -> Result<Option<T>, LoaderError>,
+    {
+        let lower_name = name.to_ascii_lowercase();
+        let mounts = self
+            .sources
+            .iter()
+            .enumerate()
+            .filter(|(_, mount)| include(mount));
+        for (index, mount) in mounts {
+            let source = mount.source.as_ref();
+            if let Some(value) = f(source, name)? {
+                return Ok(Some(Found {
+                    value,
+                    source: index,
+                    path: name.into(),
+                    kind: MatchKind::Exact,
+                }));
+            }
+
+            // paths are matched case-insensitively within each source, like the engine does
+            let stored = mount.index.get(source, &lower_name);
+            let stored = stored.as_deref().unwrap_or(&lower_name);
+            if stored != name {
+                if let Some(value) = f(source, stored)? {
+                    return Ok(Some(Found {
+                        value,
+                        source: index,
+                        path: stored.into(),
+                        kind: MatchKind::CaseInsensitive,
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// List all files that start with `prefix` across all sources.
+    ///
+    /// The returned paths are sorted and de-duplicated.
+    pub fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        self.list_with_case(prefix, NormalizeCase::Preserve)
+    }
+
+    /// List all files that start with `prefix` across all sources, with the specified case normalization.
+    pub fn list_with_case(
+        &self,
+        prefix: &str,
+        case: NormalizeCase,
+    ) -> Result<Vec<String>, LoaderError> {
+        let prefix = clean_path(prefix);
+        let lower_prefix = prefix.to_ascii_lowercase();
+        let mut paths = BTreeSet::new();
+        for mount in self.sources.iter() {
+            let source = mount.source.as_ref();
+            let mut listed = if source.is_packed() && prefix != lower_prefix {
+                // packed sources match case-insensitively, like when loading
+                let mut listed = source.list("")?;
+                listed.retain(|path| strip_prefix_ignore_case(path, &prefix).is_some());
+                listed
+            } else {
+                source.list(&prefix)?
+            };
+            if !source.is_packed() && prefix != lower_prefix {
+                listed.extend(source.list(&lower_prefix)?);
+            }
+            for path in listed {
+                match case {
+                    NormalizeCase::Preserve => paths.insert(path),
+                    NormalizeCase::Lower => paths.insert(path.to_ascii_lowercase()),
+                };
+            }
+        }
+        Ok(paths.into_iter().collect())
+    }
+
+    /// Iterate over every file in all sources.
+    ///
+    /// Files present in multiple sources are only yielded once, with the path as stored in the first source
+    /// containing it. Paths are compared case-insensitively. Each source is listed when the iterator reaches it,
+    /// sources that fail to list yield their error and iteration continues with the next source.
+    pub fn iter_files(&self) -> impl Iterator<Item = Result<String, LoaderError>> + '_ {
+        let mut seen = HashSet::new();
+        self.sources
+            .iter()
+            .flat_map(|mount| match mount.source.list("") {
+                Ok(paths) => paths.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            })
+            .filter(move |path| match path {
+                Ok(path) => seen.insert(path.to_ascii_lowercase()),
+                Err(_) => true,
+            })
+    }
+
+    /// List all files matching a glob pattern across all sources, like `materials/models/player/*.vmt`.
+    ///
+    /// `*` and `?` don't match across directories, use `**` to match any number of directories.
+    /// The returned paths are sorted and de-duplicated.
+    pub fn list_glob(&self, pattern: &str) -> Result<Vec<String>, LoaderError> {
+        let pattern = clean_path(pattern);
+        let literal = pattern
+            .find(['*', '?', '['])
+            .map_or(pattern.as_ref(), |wildcard| &pattern[..wildcard]);
+        let matcher = Pattern::new(&pattern)?;
+        let mut paths = self.list(literal)?;
+        paths.retain(|path| matcher.matches_with(path, GLOB_OPTIONS));
+        Ok(paths)
+    }
+
+    /// Find all files matching a glob pattern across all sources, the same as [`list_glob`](Self::list_glob).
+    pub fn find_glob(&self, pattern: &str) -> Result<Vec<String>, LoaderError> {
+        self.list_glob(pattern)
+    }
+
+    /// Enumerate all files matching a glob pattern across all sources, the same as [`list_glob`](Self::list_glob).
+    pub fn glob(&self, pattern: &str) -> Result<Vec<String>, LoaderError> {
+        self.list_glob(pattern)
+    }
+
+    /// Verify the consistency of enumeration and resolution across all sources.
+    ///
+    /// This checks that listing the loader yields no duplicate paths, and that every listed path resolves to the
+    /// first source that lists it ignoring case, the same order [`load`](Self::load) searches the sources in.
+    /// This lists the full contents of every source and is intended as a testing aid.
+    pub fn debug_verify(&self) -> Result<(), LoaderError> {
+        for case in [NormalizeCase::Preserve, NormalizeCase::Lower] {
+            let paths = self.list_with_case("", case)?;
+            if let Some(pair) = paths.windows(2).find(|pair| pair[0] >= pair[1]) {
+                return Err(LoaderError::Other(format!(
+                    "enumeration with {case:?} case is not sorted or contains duplicates: {} and {}",
+                    pair[0], pair[1]
+                )));
+            }
+        }
+
+        // each source is searched ignoring case before moving on to the next
+        let mut owners: Vec<(String, usize, String)> = Vec::new();
+        for (index, mount) in self.sources.iter().enumerate() {
+            let paths = mount.source.list("")?;
+            owners.extend(
+                paths
+                    .into_iter()
+                    .map(|path| (path.to_ascii_lowercase(), index, path)),
+            );
+        }
+        // keep the first source listing each path
+        owners.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+        owners.dedup_by(|a, b| a.0 == b.0);
+
+        for (_, owner, path) in owners {
+            let found = self.find(&path, |source, path| Ok(source.has(path)?.then_some(())))?;
+            match found {
+                Some(found) if found.source == owner => {}
+                Some(found) => {
+                    return Err(LoaderError::Other(format!(
+                        "{path} is listed first by source {owner} but resolves to source {}",
+                        found.source
+                    )));
+                }
+                None => {
+                    return Err(LoaderError::Other(format!(
+                        "{path} is listed by source {owner} but doesn't resolve"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Look for a file by name in one or more paths, returning the path as it's stored in the source.
+    ///
+    /// Errors while searching the sources are treated as the file not being found, use
+    /// [`find_in_paths_ext`](Self::find_in_paths_ext) to handle them.
+    pub fn find_in_paths<S: Display>(&self, name: &str, paths: &[S]) -> Option<String> {
+        self.find_in_paths_ext(name, paths, &[""])
+            .unwrap_or_default()
+    }
+
+    /// Look for a file by name in one or more paths, trying each of the provided extensions.
+    ///
+    /// Every combination of path and extension is tried, in the order of the paths.
+    /// The extensions should include the leading `.`. Returns the first path that exists, as it's stored in the
+    /// source that contains it.
+    pub fn find_in_paths_ext<S: Display>(
+        &self,
+        stem: &str,
+        paths: &[S],
+        exts: &[&str],
+    ) -> Result<Option<String>, LoaderError> {
+        self.try_paths(stem, paths, exts, |path| {
+            let found = self.find(path, |source, path| Ok(source.has(path)?.then_some(())))?;
+            Ok(found.map(|found| found.path))
+        })
+    }
+
+    /// Load a file by name from the first of one or more paths that contains it.
+    ///
+    /// The paths are tried the same way as with [`find_in_paths`](Self::find_in_paths),
+    /// without having to search the sources again to load the found file.
+    pub fn load_from_paths<S: Display>(
+        &self,
+        name: &str,
+        paths: &[S],
+    ) -> Result<Option<Vec<u8>>, LoaderError> {
+        self.try_paths(name, paths, &[""], |path| self.load(path))
+    }
+
+    /// Call `f` with every combination of path and extension for the stem until it returns a result.
+    ///
+    /// The sources are already searched ignoring case, so the stem isn't retried in lowercase.
+    fn try_paths<S, T, F>(
+        &self,
+        stem: &str,
+        paths: &[S],
+        exts: &[&str],
+        mut f: F,
+    ) -> Result<Option<T>, LoaderError>
+    where
+        S: Display,
+        F: FnMut(&str) -> Result<Option<T>, LoaderError>,
+    {
+        for path in paths {
+            for ext in exts {
+                let full_path = format!("{}{}{}", path, stem, ext);
+                if let Some(found) = f(&clean_path(&full_path))? {
+                    return Ok(Some(found));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Mount a loader as a single source in another loader.
+///
+/// A loader added as a source is a snapshot of the sources it had at the time, so sources mounted in the inner
+/// loader afterwards are not visible to the outer loader. Adding a clone of a loader to itself is thus
+/// safe and doesn't cause infinite recursion.
+impl AssetSource for Loader {
+    fn has(&self, path: &str) -> Result<bool, LoaderError> {
+        self.exists(path)
+    }
+
+    fn describe(&self) -> String {
+        String::from("loader")
+    }
+
+    fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        Loader::load(self, path)
+    }
+
+    fn load_shared(&self, path: &str) -> Result<Option<Arc<[u8]>>, LoaderError> {
+        Loader::load_shared(self, path)
+    }
+
+    #[cfg(feature = "mmap")]
+    fn load_mmap(&self, path: &str) -> Result<Option<MappedBytes>, LoaderError> {
+        Loader::load_mmap(self, path)
+    }
+
+    fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        Loader::open(self, path)
+    }
+
+    fn load_range(&self, path: &str, range: Range<u64>) -> Result<Option<Vec<u8>>, LoaderError> {
+        Loader::load_range(self, path, range)
+    }
+
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        Loader::size(self, path)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+        Ok(Loader::stat(self, path)?.map(|(metadata, _)| metadata))
+    }
+
+    fn crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        Loader::crc(self, path)
+    }
+
+    fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        Loader::stored_crc(self, path)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        Loader::list(self, prefix)
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = path.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &path[prefix.len()..])
+}
+
+fn strip_suffix_ignore_case<'a>(path: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = path.len().checked_sub(suffix.len())?;
+    let tail = path.get(split..)?;
+    tail.eq_ignore_ascii_case(suffix).then(|| &path[..split])
+}
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Directories prepended to paths by file extension when auto prefixing is enabled
+const AUTO_PREFIXES: &[(&str, &str)] = &[
+    (".mdl", "models/"),
+    (".vmt", "materials/"),
+    (".vtf", "materials/"),
+    (".wav", "sound/"),
+    (".mp3", "sound/"),
+];
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Maximum nesting of loaders resolving paths through each other before assuming a cycle
+const MAX_RESOLVE_DEPTH: usize = 64;
+
+thread_local! {
+    static RESOLVE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Tracks how deeply path resolution is nested on the current thread, to catch sources that resolve through themselves
+struct ResolveDepth;
+
+impl ResolveDepth {
+    fn enter(name: &str) -> Result<Self, LoaderError> {
+        let depth = RESOLVE_DEPTH.get();
+        if depth >= MAX_RESOLVE_DEPTH {
+            return Err(LoaderError::Other(format!(
+                "source resolution cycle detected while resolving {name}"
+            )));
+        }
+        RESOLVE_DEPTH.set(depth + 1);
+        Ok(ResolveDepth)
+    }
+}
+
+impl Drop for ResolveDepth {
+    fn drop(&mut self) {
+        RESOLVE_DEPTH.set(RESOLVE_DEPTH.get() - 1);
+    }
+}
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Marks references for the engine itself instead of file paths
+const INTERNAL_MARKER: char = '!';
+
+/// Prefixes paths for engine internal caching, this is not part of the path
+const INTERNAL_PREFIX: char = '@';
+
+/// Normalize a path for lookup.
+///
+/// Backslashes, as found in paths from model data, are replaced with forward slashes, repeated slashes are collapsed
+/// and `.` and `..` segments are resolved. `..` segments that would go above the root are kept, sources reject
+/// such paths. A leading `@`, used by the engine for cached materials, is stripped. References starting with `!`, like sentence
+/// names in sound scripts, are engine internal and handled by [`is_internal_reference`].
+pub(crate) fn clean_path(path: &str) -> Cow<'_, str> {
+    let path = path.trim_start_matches(INTERNAL_PREFIX);
+    let path = normalize_separators(path);
+    if !path
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return path;
+    }
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "." => {}
+            ".." if segments
+                .last()
+                .is_some_and(|last| !matches!(*last, ".." | "")) =>
+            {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/").into()
+}
+
+/// Replace backslashes with forward slashes and collapse repeated slashes
+fn normalize_separators(path: &str) -> Cow<'_, str> {
+    if !path.contains('\\') && !path.contains("//") {
+        return path.into();
+    }
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars() {
+        let c = if c == '\\' { '/' } else { c };
+        if c == '/' && normalized.ends_with('/') {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized.into()
+}
+
+/// Check if a reference is an engine internal marker that never refers to a file
+fn is_internal_reference(path: &str) -> bool {
+    path.starts_with(INTERNAL_MARKER)
+}
+
+#[test]
+fn test_clean_path() {
+    assert_eq!("foo/bar", clean_path("foo/bar"));
+    assert_eq!("materials/foo.vmt", clean_path("@materials/foo.vmt"));
+    assert!(is_internal_reference("!HG_ALERT"));
+    assert!(!is_internal_reference("sound/foo!.wav"));
+    assert_eq!("foo/bar", clean_path("foo/asd/../bar"));
+    assert_eq!("../bar", clean_path("../bar"));
+    assert_eq!("../b", clean_path("a/../../b"));
+    assert_eq!("", clean_path("a/.."));
+    assert_eq!("a/b", clean_path("./a/./b"));
+    assert_eq!("/../etc", clean_path("/../etc"));
+    assert_eq!("models/props/foo.mdl", clean_path("models\\props\\foo.mdl"));
+    assert_eq!("a/b", clean_path("a//b"));
+    assert_eq!("a/b", clean_path("a\\/b"));
+    assert_eq!(
+        "models/bar/foo.mdl",
+        clean_path("models\\props//..\\bar/foo.mdl")
+    );
+}
+
+#[test]
+fn test_override_dirs() {
+    use std::collections::HashMap;
+    use std::env::join_paths;
+
+    let dirs = join_paths(["/games/modded", "/games/vanilla"]).unwrap();
+    let env = HashMap::from([
+        (TF_DIRS_VAR, dirs),
+        (TF_DIR_VAR, OsString::from("/games/tf2")),
+    ]);
+    assert_eq!(
+        Some(vec![
+            PathBuf::from("/games/modded"),
+            PathBuf::from("/games/vanilla")
+        ]),
+        override_dirs(|name| env.get(name).cloned())
+    );
+
+    let env = HashMap::from([
+        (TF_DIRS_VAR, OsString::new()),
+        (TF_DIR_VAR, OsString::from("/games/tf2")),
+    ]);
+    assert_eq!(
+        Some(vec![PathBuf::from("/games/tf2")]),
+        override_dirs(|name| env.get(name).cloned())
+    );
+    assert_eq!(None, override_dirs(|_| None));
+}
+
+#[test]
+fn test_find_app_in() {
+    use std::fs::{create_dir_all, write};
+
+    let home = tempfile::tempdir().unwrap();
+    let candidates = steam_dir_candidates(home.path());
+    let flatpak = &candidates[0];
+    create_dir_all(flatpak.join("steamapps/common/Team Fortress 2")).unwrap();
+    write(
+        flatpak.join("steamapps/libraryfolders.vdf"),
+        format!(
+            "\"libraryfolders\"\n{{\n\"0\"\n{{\n\"path\" \"{}\"\n}}\n}}\n",
+            flatpak.display()
+        ),
+    )
+    .unwrap();
+    write(
+        flatpak.join("steamapps/appmanifest_440.acf"),
+        "\"AppState\"\n{\n\"appid\" \"440\"\n\"installdir\" \"Team Fortress 2\"\n}\n",
+    )
+    .unwrap();
+    // a steam directory without the app installed
+    create_dir_all(candidates[1].join("steamapps")).unwrap();
+
+    assert_eq!(
+        Some(flatpak.join("steamapps/common/Team Fortress 2")),
+        find_app_in(&candidates, TF2_APP_ID)
+    );
+    assert_eq!(None, find_app_in(&candidates, 240));
+}
+
+#[test]
+fn test_internal_prefixes() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials")).unwrap();
+    write(dir.path().join("materials/foo.vmt"), "foo").unwrap();
+    write(dir.path().join("!marker"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    assert_eq!(
+        Some(b"foo".to_vec()),
+        loader.load("@materials/foo.vmt").unwrap()
+    );
+    assert!(!loader.exists("!marker").unwrap());
+    assert!(loader.load("!HG_ALERT").unwrap().is_none());
+}
+
+#[test]
+fn test_stat() {
+    use source::MemorySource;
+    use std::fs::write;
+
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path().join("loose.txt"), "loose").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    loader.add_source(MemorySource::from_iter([("memory.txt", "in memory")]));
+
+    let (metadata, source) = loader.stat("Loose.txt").unwrap().unwrap();
+    assert_eq!(5, metadata.size);
+    assert!(metadata.modified.is_some());
+    assert_eq!(0, source.index());
+    assert!(source.description().starts_with("dir:"));
+
+    let (metadata, source) = loader.stat("memory.txt").unwrap().unwrap();
+    assert_eq!(9, metadata.size);
+    assert_eq!(None, metadata.modified);
+    assert_eq!(1, source.index());
+    assert!(loader.stat("missing.txt").unwrap().is_none());
+}
+
+#[test]
+fn test_exists_detailed() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/foo")).unwrap();
+    write(dir.path().join("materials/foo/bar.vmt"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+
+    assert_eq!(
+        Some(MatchKind::Exact),
+        loader.exists_detailed("materials/foo/bar.vmt").unwrap()
+    );
+    assert_eq!(
+        Some(MatchKind::CaseInsensitive),
+        loader.exists_detailed("materials/Foo/Bar.vmt").unwrap()
+    );
+    assert_eq!(
+        None,
+        loader.exists_detailed("materials/foo/baz.vmt").unwrap()
+    );
+}
+
+#[test]
+fn test_find_in_paths_ext() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/models/foo")).unwrap();
+    write(dir.path().join("materials/models/foo/bar.vtf"), "bar").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+
+    let paths = ["materials/", "materials/models/"];
+    assert_eq!(
+        Some("materials/models/foo/bar.vtf".to_string()),
+        loader
+            .find_in_paths_ext("foo/bar", &paths, &[".vmt", ".vtf"])
+            .unwrap()
+    );
+    // the path is returned as it's stored in the source
+    assert_eq!(
+        Some("materials/models/foo/bar.vtf".to_string()),
+        loader
+            .find_in_paths_ext("Foo/Bar", &paths, &[".vtf"])
+            .unwrap()
+    );
+    assert_eq!(
+        Some("materials/models/foo/bar.vtf".to_string()),
+        loader.find_in_paths("Foo/Bar.vtf", &paths)
+    );
+    assert_eq!(
+        None,
+        loader
+            .find_in_paths_ext("foo/bar", &paths, &[".vmt"])
+            .unwrap()
+    );
+    assert_eq!(
+        Some(b"bar".to_vec()),
+        loader.load_from_paths("foo/bar.vtf", &paths).unwrap()
+    );
+    assert_eq!(None, loader.load_from_paths("foo/bar.vmt", &paths).unwrap());
+}
+
+#[test]
+fn test_load_string() {
+    let mut loader = Loader::empty();
+    loader.add_source(source::MemorySource::from_iter([
+        ("scripts/plain.txt", b"plain".to_vec()),
+        ("scripts/bom.txt", b"\xef\xbb\xbfbom".to_vec()),
+        ("scripts/binary.txt", b"\xff\xfe".to_vec()),
+    ]));
+    assert_eq!(
+        Some("plain".to_string()),
+        loader.load_string("scripts/plain.txt").unwrap()
+    );
+    assert_eq!(
+        Some("bom".to_string()),
+        loader.load_string("scripts/bom.txt").unwrap()
+    );
+    assert!(matches!(
+        loader.load_string("scripts/binary.txt"),
+        Err(LoaderError::Other(_))
+    ));
+    assert_eq!(None, loader.load_string("scripts/missing.txt").unwrap());
+}
+
+#[test]
+fn test_load_shared() {
+    let mut loader = Loader::empty();
+    loader.add_source(source::MemorySource::from_iter([(
+        "materials/foo.vmt",
+        "foo",
+    )]));
+    let first = loader.load_shared("materials/foo.vmt").unwrap().unwrap();
+    let second = loader.load_shared("materials/foo.vmt").unwrap().unwrap();
+    assert_eq!(b"foo".as_slice(), first.as_ref());
+    // memory sources hand out their buffers without copying
+    assert!(Arc::ptr_eq(&first, &second));
+
+    let loader = loader.with_cache(1024);
+    let first = loader.load_shared("materials/foo.vmt").unwrap().unwrap();
+    let second = loader
+        .load_shared("materials/../materials/foo.vmt")
+        .unwrap()
+        .unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(
+        loader
+            .load_shared("materials/missing.vmt")
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_evict() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/foo")).unwrap();
+    write(dir.path().join("materials/foo/bar.vmt"), "bar").unwrap();
+    write(dir.path().join("materials/foo/baz.vmt"), "baz").unwrap();
+
+    let mut loader = Loader::empty().with_cache(1024);
+    loader.add_source(dir.path().to_path_buf());
+    loader.load("materials/foo/bar.vmt").unwrap();
+    loader.load("materials/foo/baz.vmt").unwrap();
+
+    // cached data is served even after the underlying file changed
+    write(dir.path().join("materials/foo/bar.vmt"), "new").unwrap();
+    write(dir.path().join("materials/foo/baz.vmt"), "new").unwrap();
+    assert_eq!(
+        Some(b"bar".to_vec()),
+        loader.load("materials/foo/bar.vmt").unwrap()
+    );
+
+    assert!(loader.evict("materials/foo/bar.vmt"));
+    assert!(!loader.evict("materials/foo/bar.vmt"));
+    assert_eq!(
+        Some(b"new".to_vec()),
+        loader.load("materials/foo/bar.vmt").unwrap()
+    );
+
+    assert_eq!(2, loader.evict_matching("materials/**/*.vmt").unwrap());
+    assert_eq!(
+        Some(b"new".to_vec()),
+        loader.load("materials/foo/baz.vmt").unwrap()
+    );
+}
+
+#[test]
+fn test_resolve_texture() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/models/foo")).unwrap();
+    write(dir.path().join("materials/models/foo/bar.vtf"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+
+    for name in [
+        "models/foo/bar",
+        "models/foo/bar.vtf",
+        "materials/models/foo/bar.vtf",
+        "Materials/models/foo/bar.VTF",
+        "/models/foo/bar",
+    ] {
+        assert_eq!(
+            Some("materials/models/foo/bar.vtf".to_string()),
+            loader.resolve_texture(name).unwrap(),
+            "{name}"
+        );
+    }
+    assert_eq!(None, loader.resolve_texture("models/foo/missing").unwrap());
+}
+
+#[test]
+fn test_loader_as_source() {
+    use std::fs::write;
+
+    let base = tempfile::tempdir().unwrap();
+    let mod_dir = tempfile::tempdir().unwrap();
+    write(base.path().join("both.txt"), "base").unwrap();
+    write(base.path().join("base.txt"), "base").unwrap();
+    write(mod_dir.path().join("both.txt"), "mod").unwrap();
+
+    let mut base_loader = Loader::empty();
+    base_loader.add_source(base.path().to_path_buf());
+
+    let mut loader = Loader::empty();
+    loader.add_source(mod_dir.path().to_path_buf());
+    loader.add_source(base_loader.clone());
+    assert_eq!(Some(b"mod".to_vec()), loader.load("both.txt").unwrap());
+    assert_eq!(Some(b"base".to_vec()), loader.load("base.txt").unwrap());
+    assert_eq!(vec!["base.txt", "both.txt"], loader.list("").unwrap());
+
+    base_loader.add_source(base_loader.clone());
+    assert!(!base_loader.exists("missing.txt").unwrap());
+    loader.debug_verify().unwrap();
+}
+
+#[test]
+fn test_debug_verify() {
+    use source::MemorySource;
+
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([("foo.txt", "lower")]));
+    loader.add_source(MemorySource::from_iter([
+        ("Foo.txt", "upper"),
+        ("bar.txt", ""),
+    ]));
+    assert_eq!(Some(b"lower".to_vec()), loader.load("Foo.txt").unwrap());
+    loader.debug_verify().unwrap();
+
+    // the source listing the path doesn't contain it
+    struct Phantom;
+    impl AssetSource for Phantom {
+        fn has(&self, _path: &str) -> Result<bool, LoaderError> {
+            Ok(false)
+        }
+
+        fn load(&self, _path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            Ok(None)
+        }
+
+        fn list(&self, _prefix: &str) -> Result<Vec<String>, LoaderError> {
+            Ok(vec![String::from("phantom.txt")])
+        }
+    }
+    loader.add_source(Phantom);
+    assert!(matches!(
+        loader.debug_verify(),
+        Err(LoaderError::Other(error)) if error.contains("phantom.txt")
+    ));
+}
+
+#[test]
+fn test_cached_loader_as_source() {
+    use source::MemorySource;
+
+    let mut base = Loader::empty().with_cache(1024);
+    base.add_source(MemorySource::from_iter([("both.txt", "base")]));
+
+    // mounting a cached loader into a clone of itself doesn't deadlock on a cache miss
+    let mut nested = base.clone();
+    nested.add_source(base.clone());
+    assert_eq!(Some(b"base".to_vec()), nested.load("both.txt").unwrap());
+    assert_eq!(None, nested.load("missing.txt").unwrap());
+
+    // clones with different sources don't share cached data
+    let mut modded = base.clone();
+    modded.insert_source_front(MemorySource::from_iter([("both.txt", "mod")]));
+    assert_eq!(Some(b"base".to_vec()), base.load("both.txt").unwrap());
+    assert_eq!(Some(b"mod".to_vec()), modded.load("both.txt").unwrap());
+    assert_eq!(Some(b"base".to_vec()), base.load("both.txt").unwrap());
+}
+
+#[test]
+fn test_list_normalize_case() {
+    use std::fs::{create_dir_all, write};
+
+    let first = tempfile::tempdir().unwrap();
+    let second = tempfile::tempdir().unwrap();
+    create_dir_all(first.path().join("materials/Foo")).unwrap();
+    create_dir_all(second.path().join("materials/foo")).unwrap();
+    write(first.path().join("materials/Foo/Bar.vmt"), "").unwrap();
+    write(second.path().join("materials/foo/bar.vmt"), "").unwrap();
+    write(second.path().join("materials/foo/baz.vmt"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(first.path().to_path_buf());
+    loader.add_source(second.path().to_path_buf());
+
+    assert_eq!(
+        vec![
+            "materials/Foo/Bar.vmt",
+            "materials/foo/bar.vmt",
+            "materials/foo/baz.vmt"
+        ],
+        loader.list("materials/").unwrap()
+    );
+    assert_eq!(
+        vec!["materials/foo/bar.vmt", "materials/foo/baz.vmt"],
+        loader
+            .list_with_case("materials/", NormalizeCase::Lower)
+            .unwrap()
+    );
+}
+
+pub(crate) const TF2_APP_ID: u32 = 440;
+
+/// Steam install locations relative to the home directory, for layouts that aren't always detected
+const STEAM_DIRS: &[&str] = &[
+    ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+    ".steam/steam",
+    ".local/share/Steam",
+];
+
+fn steam_dir_candidates(home: &Path) -> Vec<PathBuf> {
+    STEAM_DIRS.iter().map(|dir| home.join(dir)).collect()
+}
+
+fn find_app_dir(steam: &SteamDir, app_id: u32) -> Option<PathBuf> {
+    let (app, library) = steam.find_app(app_id).ok()??;
+    Some(library.resolve_app_dir(&app))
+}
+
+/// Find the install directory of an app in the first steam directory that has it installed
+fn find_app_in(steam_dirs: &[PathBuf], app_id: u32) -> Option<PathBuf> {
+    steam_dirs
+        .iter()
+        .filter_map(|dir| SteamDir::from_dir(dir).ok())
+        .find_map(|steam| find_app_dir(&steam, app_id))
+}
+
+/// Environment variable with a list of tf2 directories, separated like `PATH`
+const TF_DIRS_VAR: &str = "TF_DIRS";
+/// Environment variable with a single tf2 directory
+const TF_DIR_VAR: &str = "TF_DIR";
+
+/// Get the tf2 directories set by the environment, `TF_DIRS` takes precedence over `TF_DIR`
+fn override_dirs<F: Fn(&str) -> Option<OsString>>(var: F) -> Option<Vec<PathBuf>> {
+    if let Some(dirs) = var(TF_DIRS_VAR) {
+        let dirs: Vec<PathBuf> = split_paths(&dirs)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        if !dirs.is_empty() {
+            return Some(dirs);
+        }
+    }
+    var(TF_DIR_VAR).map(|dir| vec![dir.into()])
+}
+
+/// Find the install directories of an app, the environment overrides are only used for tf2
+///
+/// The returned list is never empty.
+fn app_paths(app_id: u32) -> Result<Vec<PathBuf>, LoaderError> {
+    if let Some(dirs) = override_dirs(|name| var_os(name)).filter(|_| app_id == TF2_APP_ID) {
+        if dirs.iter().all(|dir| dir.is_dir()) {
+            Ok(dirs)
+        } else {
+            Err(LoaderError::Tf2NotFound)
+        }
+    } else {
+        // guard against steamlocate panicking on malformed steam configuration files
+        let located = catch_unwind(|| {
+            let candidates = var_os("HOME")
+                .map(|home| steam_dir_candidates(Path::new(&home)))
+                .unwrap_or_default();
+            SteamDir::locate()
+                .ok()
+                .and_then(|steam| find_app_dir(&steam, app_id))
+                .or_else(|| find_app_in(&candidates, app_id))
+        });
+        match located {
+            Ok(path) => path.map(|path| vec![path]).ok_or(LoaderError::Tf2NotFound),
+            Err(_) => {
+                warn!(
+                    "steam install detection failed unexpectedly, use TF_DIR to set the tf2 directory"
+                );
+                Err(LoaderError::Tf2NotFound)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_is_mounted() {
+    use source::MemorySource;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut loader = Loader::empty();
+    assert_eq!(0, loader.source_count());
+    let handle = loader.add_source(dir.path().to_path_buf());
+    loader.add_source(MemorySource::default());
+    assert_eq!(2, loader.source_count());
+
+    let description = format!("dir:{}", dir.path().display());
+    assert!(loader.is_mounted(&description));
+    assert!(loader.is_mounted("memory"));
+    assert!(!loader.is_mounted("vpk:tf2_misc_dir.vpk"));
+    loader.remove_source(handle);
+    assert!(!loader.is_mounted(&description));
+    assert_eq!(1, loader.source_count());
+}
+
+#[test]
+fn test_sources() {
+    use std::fs::create_dir_all;
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+
+    let mut loader = Loader::with_tf2_dir(dir.path()).unwrap();
+    loader.add_source(source::MemorySource::new());
+    assert_eq!(Some(dir.path()), loader.tf2_dir());
+    assert_eq!(
+        vec![
+            format!("dir:{}", dir.path().join("tf").display()),
+            format!("dir:{}", dir.path().join("hl2").display()),
+            String::from("memory"),
+        ],
+        loader.sources().collect::<Vec<_>>()
+    );
+    assert_eq!(None, Loader::empty().tf2_dir());
+}
+
+#[test]
+fn test_add_layered_dirs() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let base = dir.path().join("base");
+    let patch = dir.path().join("patch");
+    create_dir_all(base.join("scripts")).unwrap();
+    create_dir_all(patch.join("scripts")).unwrap();
+    write(base.join("scripts/items.txt"), "base").unwrap();
+    write(base.join("scripts/other.txt"), "base").unwrap();
+    write(patch.join("scripts/items.txt"), "patch").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_layered_dirs(&[base, patch]);
+    assert_eq!(
+        b"patch".as_slice(),
+        loader.load("scripts/items.txt").unwrap().unwrap()
+    );
+    assert_eq!(
+        b"base".as_slice(),
+        loader.load("scripts/other.txt").unwrap().unwrap()
+    );
+
+    let (data, source) = loader
+        .load_with_source("scripts/other.txt")
+        .unwrap()
+        .unwrap();
+    assert_eq!(b"base".as_slice(), data);
+    assert_eq!(1, source.index());
+    assert!(source.to_string().starts_with("dir:"));
+    assert!(source.to_string().ends_with("base"));
+
+    let mut has_other = Vec::new();
+    loader.for_each_source(|index, source| {
+        has_other.push((index, source.has("scripts/other.txt").unwrap()))
+    });
+    assert_eq!(vec![(0, false), (1, true)], has_other);
+    assert_eq!(
+        vec![0, 1],
+        loader
+            .sources_needed_for(&["scripts/other.txt", "scripts/items.txt", "missing.txt"])
+            .unwrap()
+    );
+    assert_eq!(
+        vec![0],
+        loader.sources_needed_for(&["scripts/items.txt"]).unwrap()
+    );
+}
+
+#[test]
+fn test_refresh() {
+    use std::fs::{create_dir_all, remove_file, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    let extra = tempfile::tempdir().unwrap();
+    write(extra.path().join("extra.txt"), "extra").unwrap();
+    write(dir.path().join("tf/old.txt"), "old").unwrap();
+
+    let mut loader = Loader::with_tf2_dir(dir.path()).unwrap().with_cache(1024);
+    loader.add_source(extra.path().to_path_buf());
+    assert!(loader.load("old.txt").unwrap().is_some());
+
+    remove_file(dir.path().join("tf/old.txt")).unwrap();
+    create_dir_all(dir.path().join("tf/download")).unwrap();
+    write(dir.path().join("tf/download/new.txt"), "new").unwrap();
+    // still served from the cache
+    assert!(loader.load("old.txt").unwrap().is_some());
+    assert!(!loader.exists("new.txt").unwrap());
+
+    loader.refresh().unwrap();
+    assert!(loader.load("old.txt").unwrap().is_none());
+    assert!(loader.exists("new.txt").unwrap());
+    assert!(loader.exists("extra.txt").unwrap());
+    assert_eq!(4, loader.sources.len());
+}
+
+#[test]
+fn test_insert_source_front() {
+    use source::MemorySource;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/map.txt"), "tf").unwrap();
+
+    let mut loader = Loader::with_tf2_dir(dir.path()).unwrap();
+    let back = loader.add_source(MemorySource::from_iter([("map.txt", "back")]));
+    let front = loader.insert_source_front(MemorySource::from_iter([("map.txt", "front")]));
+    assert_eq!(Some(b"front".to_vec()), loader.load("map.txt").unwrap());
+
+    loader.refresh().unwrap();
+    assert_eq!(Some(b"front".to_vec()), loader.load("map.txt").unwrap());
+
+    assert!(loader.remove_source(front));
+    assert!(!loader.remove_source(front));
+    assert_eq!(Some(b"tf".to_vec()), loader.load("map.txt").unwrap());
+    assert!(loader.remove_source(back));
+    assert_eq!(2, loader.sources.len());
+}
+
+#[test]
+fn test_reorder_sources() {
+    use source::MemorySource;
+
+    let mut loader = Loader::empty();
+    let first = loader.add_source(MemorySource::from_iter([("a.txt", "first")]));
+    let second = loader.add_source(MemorySource::from_iter([("a.txt", "second")]));
+    let map = loader.insert_source_at(1, MemorySource::from_iter([("a.txt", "map")]));
+    assert_eq!(
+        vec![first, map, second],
+        loader
+            .source_handles()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some(b"first".to_vec()), loader.load("a.txt").unwrap());
+
+    assert!(loader.set_priority(map, Priority::Override));
+    assert_eq!(Some(b"map".to_vec()), loader.load("a.txt").unwrap());
+    assert!(loader.remove_source(map));
+    assert!(!loader.set_priority(map, Priority::Base));
+    assert_eq!(Some(b"first".to_vec()), loader.load("a.txt").unwrap());
+
+    // the new source stays in the priority group of its neighbours
+    let front = loader.insert_source_at(0, MemorySource::from_iter([("a.txt", "front")]));
+    assert_eq!(Some(b"front".to_vec()), loader.load("a.txt").unwrap());
+    loader.add_source_with_priority(
+        MemorySource::from_iter([("a.txt", "override")]),
+        Priority::Override,
+    );
+    assert_eq!(Some(b"override".to_vec()), loader.load("a.txt").unwrap());
+    assert!(loader.remove_source(front));
+    let last = loader.insert_source_at(10, MemorySource::default());
+    assert_eq!(
+        Some(last),
+        loader.source_handles().last().map(|(handle, _)| handle)
+    );
+}
+
+#[test]
+fn test_add_source_with_priority() {
+    use source::MemorySource;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/tf.txt"), "tf").unwrap();
+    write(dir.path().join("hl2/hl2.txt"), "hl2").unwrap();
+
+    let mut loader = Loader::with_tf2_dir(dir.path()).unwrap();
+    loader.add_source(MemorySource::from_iter([("base.txt", "base")]));
+    loader.add_source_with_priority(
+        MemorySource::from_iter([("hl2.txt", "game"), ("game.txt", "game")]),
+        Priority::Game,
+    );
+    loader.add_source_with_priority(
+        MemorySource::from_iter([("tf.txt", "mod")]),
+        Priority::Override,
+    );
+    loader.add_source_with_priority(
+        MemorySource::from_iter([("game.txt", "second"), ("base.txt", "second")]),
+        Priority::Game,
+    );
+    loader.add_source_with_priority(
+        MemorySource::from_iter([("tf.txt", "second")]),
+        Priority::Override,
+    );
+
+    for refresh in [false, true] {
+        if refresh {
+            loader.refresh().unwrap();
+        }
+        assert_eq!(Some(b"mod".to_vec()), loader.load("tf.txt").unwrap());
+        assert_eq!(Some(b"hl2".to_vec()), loader.load("hl2.txt").unwrap());
+        assert_eq!(Some(b"game".to_vec()), loader.load("game.txt").unwrap());
+        assert_eq!(Some(b"second".to_vec()), loader.load("base.txt").unwrap());
+    }
+}
+
+#[test]
+fn test_resolve_cycle() {
+    use std::sync::OnceLock;
+
+    /// Source that resolves through a loader it is mounted in
+    struct Delegate(Arc<OnceLock<Loader>>);
+
+    impl AssetSource for Delegate {
+        fn has(&self, path: &str) -> Result<bool, LoaderError> {
+            self.0.get().unwrap().exists(path)
+        }
+
+        fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            self.0.get().unwrap().load(path)
+        }
+    }
+
+    let inner = Arc::new(OnceLock::new());
+    let mut loader = Loader::empty();
+    loader.add_source(Delegate(inner.clone()));
+    inner.set(loader.clone()).ok();
+
+    let error = loader.load("foo.txt").unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("source resolution cycle detected")
+    );
+    assert!(loader.exists("foo.txt").is_err());
+
+    // the depth is reset after the error
+    let mut loader = Loader::empty();
+    loader.add_source(PathBuf::from("/nonexistent"));
+    assert!(!loader.exists("foo.txt").unwrap());
+}
+
+#[test]
+fn test_write_to() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("scripts")).unwrap();
+    write(dir.path().join("scripts/items.txt"), "items").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    let mut out = Vec::new();
+    assert!(loader.write_to("scripts/items.txt", &mut out).unwrap());
+    assert!(!loader.write_to("scripts/missing.txt", &mut out).unwrap());
+    // directories aren't files
+    assert!(!loader.write_to("scripts", &mut out).unwrap());
+    assert_eq!(b"items".as_slice(), out);
+    assert_eq!(Some(5), loader.size("scripts/items.txt").unwrap());
+    assert_eq!(
+        Some(b"tem".to_vec()),
+        loader.load_range("scripts/items.txt", 1..4).unwrap()
+    );
+    assert_eq!(
+        Some(Vec::new()),
+        loader.load_range("scripts/items.txt", 10..20).unwrap()
+    );
+    assert_eq!(None, loader.size("scripts").unwrap());
+
+    let (data, hash) = loader.load_and_hash("scripts/items.txt").unwrap().unwrap();
+    assert_eq!(b"items".as_slice(), data);
+    assert_eq!(0x3e7884bf4f412c6f, hash);
+    assert!(
+        loader
+            .load_and_hash("scripts/missing.txt")
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_load_from_labels() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("tf")).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    write(dir.path().join("tf/both.txt"), "tf").unwrap();
+    write(dir.path().join("hl2/both.txt"), "hl2").unwrap();
+    let mod_dir = tempfile::tempdir().unwrap();
+    write(mod_dir.path().join("both.txt"), "mod").unwrap();
+
+    let mut loader = Loader::with_tf2_dir(dir.path()).unwrap();
+    loader.add_labeled_source("mymod", mod_dir.path().to_path_buf());
+    assert_eq!(Some(b"tf".to_vec()), loader.load("both.txt").unwrap());
+    assert_eq!(
+        Some(b"mod".to_vec()),
+        loader.load_from_labels("both.txt", &["mymod"]).unwrap()
+    );
+    assert_eq!(
+        Some(b"hl2".to_vec()),
+        loader
+            .load_from_labels("both.txt", &["hl2", "mymod"])
+            .unwrap()
+    );
+    assert_eq!(
+        None,
+        loader.load_from_labels("both.txt", &["other"]).unwrap()
+    );
+}
+
+#[test]
+fn test_auto_prefix() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    for path in [
+        "models/props/foo.mdl",
+        "materials/props/foo.vmt",
+        "materials/props/foo.vtf",
+        "sound/props/foo.wav",
+        "sound/props/foo.mp3",
+        "scripts/foo.txt",
+    ] {
+        let path = dir.path().join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, "").unwrap();
+    }
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    assert!(!loader.exists("props/foo.mdl").unwrap());
+
+    loader.set_auto_prefix(true);
+    for name in [
+        "props/foo.mdl",
+        "props/foo.vmt",
+        "props/foo.vtf",
+        "props/foo.wav",
+        "Props/Foo.MP3",
+    ] {
+        assert!(loader.exists(name).unwrap(), "{name}");
+    }
+    assert!(loader.exists("models/props/foo.mdl").unwrap());
+    assert!(!loader.exists("foo.txt").unwrap());
+    // the prefix isn't added twice
+    assert!(!loader.exists("models/foo.mdl").unwrap());
+}
+
+#[test]
+fn test_iter_files() {
+    use source::MemorySource;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/models")).unwrap();
+    write(dir.path().join("materials/models/foo.vmt"), "").unwrap();
+    write(dir.path().join("materials/Bar.vmt"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    loader.add_source(MemorySource::from_iter([
+        ("materials/bar.vmt", ""),
+        ("materials/models/foo.vmt", ""),
+        ("scripts/items.txt", ""),
+    ]));
+
+    let mut files = loader.iter_files().collect::<Result<Vec<_>, _>>().unwrap();
+    files.sort();
+    assert_eq!(
+        vec![
+            "materials/Bar.vmt",
+            "materials/models/foo.vmt",
+            "scripts/items.txt"
+        ],
+        files
+    );
+}
+
+#[test]
+fn test_list_glob() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("materials/models/player/scout")).unwrap();
+    write(dir.path().join("materials/models/player/a.vmt"), "").unwrap();
+    write(dir.path().join("materials/models/player/a.vtf"), "").unwrap();
+    write(dir.path().join("materials/models/player/scout/b.vmt"), "").unwrap();
+    write(dir.path().join("materials/models/player/c_a.vmt"), "").unwrap();
+    write(dir.path().join("materials/models/player/c_ab.vmt"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    assert_eq!(
+        vec![
+            "materials/models/player/a.vmt",
+            "materials/models/player/c_a.vmt",
+            "materials/models/player/c_ab.vmt"
+        ],
+        loader.list_glob("materials/models/player/*.vmt").unwrap()
+    );
+    assert_eq!(
+        vec!["materials/models/player/c_a.vmt"],
+        loader.list_glob("materials/models/player/c_?.vmt").unwrap()
+    );
+    assert!(
+        loader
+            .list_glob("materials/models/?/a.vmt")
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        vec![
+            "materials/models/player/a.vmt",
+            "materials/models/player/c_a.vmt",
+            "materials/models/player/c_ab.vmt",
+            "materials/models/player/scout/b.vmt"
+        ],
+        loader.list_glob("materials/**/*.vmt").unwrap()
+    );
+    // mixed case prefixes fall back to lowercase, like loading
+    assert_eq!(5, loader.list("Materials/Models/").unwrap().len());
+    assert!(loader.list_glob("materials/[").is_err());
+    assert_eq!(
+        vec![
+            "materials/models/player/c_a.vmt",
+            "materials/models/player/c_ab.vmt"
+        ],
+        loader.find_glob("materials/**/c_*.vmt").unwrap()
+    );
+    assert_eq!(
+        vec!["materials/models/player/scout/b.vmt"],
+        loader.glob("materials/**/scout/*").unwrap()
+    );
+}
+
+#[test]
+#[cfg(feature = "zip")]
+fn test_mount_zip() {
+    use zip::write::{FileOptions, ZipWriter};
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("scripts/zipped.txt", FileOptions::default())
+        .unwrap();
+    zip.write_all(b"zipped").unwrap();
+    zip.start_file(
+        "scripts/stored.txt",
+        FileOptions::default().compression_method(zip::CompressionMethod::Stored),
+    )
+    .unwrap();
+    zip.write_all(b"stored").unwrap();
+    let data = zip.finish().unwrap().into_inner();
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("test.zip"), &data).unwrap();
+
+    let mut loader = Loader::empty();
+    let handle = loader.mount_zip(dir.path().join("test.zip")).unwrap();
+    assert_eq!(
+        Some(b"zipped".to_vec()),
+        loader.load("scripts/zipped.txt").unwrap()
+    );
+    for name in ["scripts/zipped.txt", "scripts/stored.txt"] {
+        let mut data = Vec::new();
+        let mut reader = loader.open(name).unwrap().unwrap();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(loader.load(name).unwrap().unwrap(), data);
+    }
+    assert_eq!(vec!["zip:test.zip"], loader.sources().collect::<Vec<_>>());
+    assert!(loader.remove_source(handle));
+
+    loader.mount_zip_reader(Cursor::new(data)).unwrap();
+    assert_eq!(
+        Some(b"zipped".to_vec()),
+        loader.load("scripts/zipped.txt").unwrap()
+    );
+    assert!(loader.mount_zip(dir.path().join("missing.zip")).is_err());
+    assert!(loader.mount_zip_reader(Cursor::new(b"not a zip")).is_err());
+}
+
+#[test]
+fn test_with_path_cache() {
+    use source::MemorySource;
+
+    let mut loader = Loader::empty().with_cache(1024).with_path_cache(16);
+    loader.add_source(MemorySource::from_iter([("models/foo.mdl", "mdl")]));
+    for name in [
+        "models\\foo.mdl",
+        "models/bar/../foo.mdl",
+        "models\\foo.mdl",
+    ] {
+        assert_eq!(Some(b"mdl".to_vec()), loader.load(name).unwrap());
+        assert_eq!(Some(3), loader.size(name).unwrap());
+    }
+    assert!(loader.evict("models//foo.mdl"));
+}
+
+#[test]
+fn test_load_timed() {
+    use source::MemorySource;
+    use std::fs::read_to_string;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([("a.txt", "a")]));
+    loader.add_source(MemorySource::from_iter([("b.txt", "b")]));
+
+    let trace = dir.path().join("trace.txt");
+    loader.start_trace(&trace).unwrap();
+    let (data, timing) = loader.load_timed("B.txt").unwrap().unwrap();
+    assert!(loader.load_timed("missing.txt").unwrap().is_none());
+    loader.stop_trace().unwrap();
+
+    assert_eq!(b"b".to_vec(), data);
+    assert_eq!(1, timing.source);
+    assert_eq!(MatchKind::CaseInsensitive, timing.kind);
+    assert_eq!("b.txt\t1\n", read_to_string(trace).unwrap());
+}
+
+#[test]
+fn test_load_many() {
+    use source::MemorySource;
+
+    /// Source that fails to load a path
+    struct Broken;
+
+    impl AssetSource for Broken {
+        fn has(&self, path: &str) -> Result<bool, LoaderError> {
+            Ok(path == "models/broken.mdl")
+        }
+
+        fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            match path {
+                "models/broken.mdl" => Err(LoaderError::Other("broken".into())),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    let mut loader = Loader::empty();
+    loader.add_source(Broken);
+    loader.add_source(MemorySource::from_iter([
+        ("models/foo.mdl", "mdl"),
+        ("models/foo.vvd", "vvd"),
+    ]));
+    let names = vec![
+        "models/foo.vvd",
+        "models/broken.mdl",
+        "models/foo.phy",
+        "MODELS/foo.mdl",
+    ];
+    let loaded = loader.load_many(names.iter().copied());
+    assert_eq!(
+        names,
+        loaded
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some(b"vvd".to_vec()), *loaded[0].1.as_ref().unwrap());
+    assert!(loaded[1].1.is_err());
+    assert_eq!(None, *loaded[2].1.as_ref().unwrap());
+    assert_eq!(Some(b"mdl".to_vec()), *loaded[3].1.as_ref().unwrap());
 }
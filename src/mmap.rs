@@ -0,0 +1,136 @@
+use crate::{Loader, LoaderError};
+use memmap2::Mmap;
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Asset data that is either memory mapped from a file or held in memory. Requires the `mmap` feature.
+///
+/// Cloning is cheap, clones share the same mapping or buffer.
+#[derive(Clone)]
+pub struct MappedBytes(Data);
+
+#[derive(Clone)]
+enum Data {
+    Mapped(Arc<Mmap>),
+    Owned(Arc<[u8]>),
+}
+
+impl MappedBytes {
+    /// Map the file into memory
+    pub(crate) fn map(file: &File) -> Result<Self, LoaderError> {
+        // mapping empty files fails on some platforms
+        if file.metadata()?.len() == 0 {
+            return Ok(Vec::new().into());
+        }
+        // SAFETY: the mapping is only read, modifying or truncating the file while it's mapped is not supported
+        let map = unsafe { Mmap::map(file)? };
+        Ok(MappedBytes(Data::Mapped(Arc::new(map))))
+    }
+
+    /// Map a section of the file into memory, the section has to be inside the file
+    #[cfg(feature = "vpk")]
+    pub(crate) fn map_range(file: &File, offset: u64, length: usize) -> Result<Self, LoaderError> {
+        if length == 0 {
+            return Ok(Vec::new().into());
+        }
+        // SAFETY: see `map`
+        let map = unsafe {
+            memmap2::MmapOptions::new()
+                .offset(offset)
+                .len(length)
+                .map(file)?
+        };
+        Ok(MappedBytes(Data::Mapped(Arc::new(map))))
+    }
+
+    /// Whether the data is memory mapped from a file
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.0, Data::Mapped(_))
+    }
+}
+
+impl Deref for MappedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Data::Mapped(map) => map,
+            Data::Owned(data) => data,
+        }
+    }
+}
+
+impl AsRef<[u8]> for MappedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for MappedBytes {
+    fn from(data: Vec<u8>) -> Self {
+        MappedBytes(Data::Owned(data.into()))
+    }
+}
+
+impl From<Arc<[u8]>> for MappedBytes {
+    fn from(data: Arc<[u8]>) -> Self {
+        MappedBytes(Data::Owned(data))
+    }
+}
+
+impl Debug for MappedBytes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MappedBytes")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+impl Loader {
+    /// Load a file by path, memory mapping it if it's a plain file in a directory source or stored in a vpk archive.
+    /// Requires the `mmap` feature.
+    ///
+    /// Vpk entries with preload data and files from other sources like zip files are loaded into memory instead, and with a
+    /// [cache](Self::with_cache), cache hits return the cached data. Mapped files are not added to the cache.
+    ///
+    /// Returns `None` if the path doesn't exist.
+    #[tracing::instrument(skip(self))]
+    pub fn load_mmap(&self, name: &str) -> Result<Option<MappedBytes>, LoaderError> {
+        let key = self.clean_name(name);
+        if let Some(data) = self.cache.as_ref().and_then(|cache| cache.get(&key)) {
+            self.trace_cache_hit(&key)?;
+            return Ok(Some(data.into()));
+        }
+        let found = self.find(&key, |source, path| source.load_mmap(path))?;
+        Ok(found.map(|found| {
+            self.record_trace(&found);
+            found.value
+        }))
+    }
+}
+
+#[test]
+fn test_load_mmap() {
+    use crate::source::MemorySource;
+    use std::fs::write;
+
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path().join("large.bsp"), "mapped").unwrap();
+    write(dir.path().join("empty.txt"), "").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    loader.add_source(MemorySource::from_iter([("packed.txt", "packed")]));
+
+    let mapped = loader.load_mmap("large.bsp").unwrap().unwrap();
+    assert!(mapped.is_mapped());
+    assert_eq!(b"mapped", &mapped[..]);
+    let packed = loader.load_mmap("packed.txt").unwrap().unwrap();
+    assert!(!packed.is_mapped());
+    assert_eq!(b"packed", &packed[..]);
+    assert!(loader.load_mmap("empty.txt").unwrap().unwrap().is_empty());
+    assert!(loader.load_mmap("missing.txt").unwrap().is_none());
+}
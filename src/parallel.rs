@@ -0,0 +1,35 @@
+use crate::{Loader, LoaderError};
+use rayon::prelude::*;
+
+impl Loader {
+    /// Check if multiple files exist, checking the paths in parallel. Requires the `rayon` feature.
+    ///
+    /// The results are in the same order as `names`. The checks run on the global rayon thread pool,
+    /// sources that lock internally, like zip archives, are still queried one path at a time.
+    pub fn exists_many(&self, names: &[&str]) -> Vec<Result<bool, LoaderError>> {
+        names.par_iter().map(|name| self.exists(name)).collect()
+    }
+}
+
+#[test]
+fn test_exists_many() {
+    use crate::source::MemorySource;
+
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([
+        ("materials/foo.vmt", ""),
+        ("materials/bar.vmt", ""),
+    ]));
+
+    let names = [
+        "materials/foo.vmt",
+        "materials/missing.vmt",
+        "materials/BAR.vmt",
+    ];
+    let found = loader
+        .exists_many(&names)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(vec![true, false, true], found);
+}
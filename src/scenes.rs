@@ -0,0 +1,104 @@
+use crate::{Loader, LoaderError, strip_prefix_ignore_case, strip_suffix_ignore_case};
+
+/// Magic bytes at the start of compiled caption files
+const CAPTION_MAGIC: &[u8; 4] = b"VCCD";
+
+/// Header of a compiled closed caption file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptionHeader {
+    pub version: u32,
+    /// Number of data blocks containing the caption text
+    pub block_count: u32,
+    pub block_size: u32,
+    /// Number of entries in the directory of caption hashes
+    pub directory_size: u32,
+    /// Offset of the first data block from the start of the file
+    pub data_offset: u32,
+}
+
+impl CaptionHeader {
+    /// Parse the header of a compiled caption file, returns `None` if the data isn't a compiled caption file
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let (magic, rest) = data.split_first_chunk::<4>()?;
+        if magic != CAPTION_MAGIC {
+            return None;
+        }
+        let mut fields = rest
+            .chunks_exact(4)
+            .map(|field| u32::from_le_bytes(field.try_into().unwrap()));
+        Some(CaptionHeader {
+            version: fields.next()?,
+            block_count: fields.next()?,
+            block_size: fields.next()?,
+            directory_size: fields.next()?,
+            data_offset: fields.next()?,
+        })
+    }
+}
+
+impl Loader {
+    /// Load the compiled closed captions for a language, like `english`, from `resource/closecaption_<lang>.dat`.
+    ///
+    /// Use [`CaptionHeader::parse`] to read the header of the loaded data.
+    pub fn load_captions(&self, lang: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        self.load(&format!(
+            "resource/closecaption_{}.dat",
+            lang.to_ascii_lowercase()
+        ))
+    }
+
+    /// Load a choreography scene by name, as referenced by entities and response rules.
+    ///
+    /// The `scenes/` prefix and `.vcd` extension are added when missing. Note that the shipped scenes are compiled
+    /// into `scenes/scenes.image`, so this only finds scenes that are stored as individual files.
+    pub fn load_scene(&self, name: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        let name = name.replace('\\', "/");
+        let name = name.trim_start_matches('/');
+        let name = strip_prefix_ignore_case(name, "scenes/").unwrap_or(name);
+        let name = strip_suffix_ignore_case(name, ".vcd").unwrap_or(name);
+        self.load(&format!("scenes/{name}.vcd"))
+    }
+}
+
+#[test]
+fn test_scenes() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("resource")).unwrap();
+    create_dir_all(dir.path().join("scenes/player/scout")).unwrap();
+    write(dir.path().join("scenes/player/scout/taunt01.vcd"), "scene").unwrap();
+    let mut captions = b"VCCD".to_vec();
+    for field in [1u32, 2, 8192, 10, 512] {
+        captions.extend_from_slice(&field.to_le_bytes());
+    }
+    write(
+        dir.path().join("resource/closecaption_english.dat"),
+        &captions,
+    )
+    .unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(dir.path().to_path_buf());
+    for name in [
+        "scenes/player/scout/taunt01.vcd",
+        "player/scout/taunt01",
+        "Scenes\\player\\scout\\taunt01.VCD",
+    ] {
+        assert_eq!(Some(b"scene".to_vec()), loader.load_scene(name).unwrap());
+    }
+
+    let captions = loader.load_captions("English").unwrap().unwrap();
+    assert_eq!(
+        Some(CaptionHeader {
+            version: 1,
+            block_count: 2,
+            block_size: 8192,
+            directory_size: 10,
+            data_offset: 512,
+        }),
+        CaptionHeader::parse(&captions)
+    );
+    assert_eq!(None, CaptionHeader::parse(b"VCCD"));
+    assert!(loader.load_captions("klingon").unwrap().is_none());
+}
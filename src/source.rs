@@ -1,7 +1,25 @@
-use crate::LoaderError;
-use std::fs::read;
-use std::io::ErrorKind;
-use std::path::PathBuf;
+#[cfg(feature = "mmap")]
+use crate::MappedBytes;
+use crate::{LoaderError, clean_path};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{File, read};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, copy, sink};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+/// Metadata of an asset in a source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssetMetadata {
+    /// Size of the asset in bytes
+    pub size: u64,
+    /// Size of the asset as stored in the source, if it's stored compressed
+    pub compressed_size: Option<u64>,
+    /// Time the asset was last modified, if the source knows it
+    pub modified: Option<SystemTime>,
+}
 
 /// Trait for the various sources that assets can be loaded from
 pub trait AssetSource {
@@ -10,39 +28,688 @@ pub trait AssetSource {
 
     /// Load an asset from the source by path if it exists
     fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError>;
+
+    /// Load an asset from the source by path if it exists, as a shared buffer
+    ///
+    /// By default this wraps the result of [`load`](Self::load), sources that keep their assets in shared buffers
+    /// override it to return them without copying.
+    fn load_shared(&self, path: &str) -> Result<Option<Arc<[u8]>>, LoaderError> {
+        Ok(self.load(path)?.map(Arc::from))
+    }
+
+    /// Open a reader over an asset from the source by path if it exists
+    ///
+    /// By default this loads the full asset into memory, sources that can read assets incrementally override it.
+    fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        Ok(self
+            .load(path)?
+            .map(|data| Box::new(Cursor::new(data)) as Box<dyn Read + Send>))
+    }
+
+    /// Load part of an asset from the source by path if it exists
+    ///
+    /// Ranges extending past the end of the asset are truncated to the end of the asset. By default this reads
+    /// the asset from [`open`](Self::open) up to the end of the range, sources that can seek override it.
+    fn load_range(&self, path: &str, range: Range<u64>) -> Result<Option<Vec<u8>>, LoaderError> {
+        let Some(mut reader) = self.open(path)? else {
+            return Ok(None);
+        };
+        Ok(Some(read_range(&mut reader, range)?))
+    }
+
+    /// Load an asset from the source by path if it exists, memory mapping the asset where possible
+    ///
+    /// By default this uses the buffer from [`load_shared`](Self::load_shared), sources backed by plain files
+    /// override it.
+    #[cfg(feature = "mmap")]
+    fn load_mmap(&self, path: &str) -> Result<Option<MappedBytes>, LoaderError> {
+        Ok(self.load_shared(path)?.map(MappedBytes::from))
+    }
+
+    /// Get the size of an asset in the source by path if it exists
+    ///
+    /// By default this loads the full asset, sources that know the size of their assets override it.
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        Ok(self.load(path)?.map(|data| data.len() as u64))
+    }
+
+    /// Get the metadata of an asset in the source by path if it exists
+    ///
+    /// By default this only reports the [`size`](Self::size), sources that know more about their assets override it.
+    fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+        Ok(self.size(path)?.map(|size| AssetMetadata {
+            size,
+            ..AssetMetadata::default()
+        }))
+    }
+
+    /// Get the CRC32 checksum of an asset in the source by path if it exists
+    ///
+    /// This is the [`stored_crc`](Self::stored_crc) if the source has one, otherwise the checksum is computed from the
+    /// loaded asset.
+    fn crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        if let Some(crc) = self.stored_crc(path)? {
+            return Ok(Some(crc));
+        }
+        Ok(self.load(path)?.map(|data| crc32(&data)))
+    }
+
+    /// Get the CRC32 checksum stored for an asset in the source by path, like archives do
+    ///
+    /// Returns `None` if the path doesn't exist or if the source doesn't store checksums, which is the default.
+    fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        let _ = path;
+        Ok(None)
+    }
+
+    /// List all paths in the source that start with `prefix`
+    ///
+    /// Sources that can't be enumerated return an empty list.
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        let _ = prefix;
+        Ok(Vec::new())
+    }
+
+    /// Short human readable description of the source, like `dir:/path/to/tf` or `vpk:tf2_misc_dir.vpk`
+    fn describe(&self) -> String {
+        String::from("source")
+    }
+
+    /// Whether this source is a packed archive, like a vpk or zip file
+    ///
+    /// Listing with a mixed case prefix matches packed sources case-insensitively, sources that return `true` should
+    /// be able to list their content.
+    fn is_packed(&self) -> bool {
+        false
+    }
+
+    /// Access the underlying vpk if this source is a vpk file
+    #[cfg(feature = "vpk")]
+    fn as_vpk(&self) -> Option<&vpk::VPK> {
+        None
+    }
 }
 
+/// Join a path to the directory, rejecting absolute paths and paths that go above the directory
+fn join_contained(dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut depth = 0usize;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => depth = depth.checked_sub(1)?,
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(dir.join(path))
+}
+
+/// Paths that are absolute or go above the directory are treated as missing
 impl AssetSource for PathBuf {
     fn has(&self, path: &str) -> Result<bool, LoaderError> {
-        Ok(self.join(path).exists())
+        Ok(join_contained(self, path).is_some_and(|path| path.exists()))
+    }
+
+    fn describe(&self) -> String {
+        format!("dir:{}", self.display())
     }
 
     fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
-        match read(self.join(path)) {
+        let Some(path) = join_contained(self, path) else {
+            return Ok(None);
+        };
+        match read(path) {
             Ok(data) => Ok(Some(data)),
             Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
             Err(e) => Err(e.into()),
         }
     }
+
+    fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        let Some(path) = join_contained(self, path) else {
+            return Ok(None);
+        };
+        match File::open(path) {
+            Ok(file) if file.metadata()?.is_file() => Ok(Some(Box::new(file))),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn load_range(&self, path: &str, range: Range<u64>) -> Result<Option<Vec<u8>>, LoaderError> {
+        let Some(path) = join_contained(self, path) else {
+            return Ok(None);
+        };
+        let mut file = match File::open(path) {
+            Ok(file) if file.metadata()?.is_file() => file,
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        file.seek(SeekFrom::Start(range.start))?;
+        Ok(Some(read_range(
+            &mut file,
+            0..range.end.saturating_sub(range.start),
+        )?))
+    }
+
+    #[cfg(feature = "mmap")]
+    fn load_mmap(&self, path: &str) -> Result<Option<MappedBytes>, LoaderError> {
+        let Some(path) = join_contained(self, path) else {
+            return Ok(None);
+        };
+        match File::open(path) {
+            Ok(file) if file.metadata()?.is_file() => Ok(Some(MappedBytes::map(&file)?)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        let Some(path) = join_contained(self, path) else {
+            return Ok(None);
+        };
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(metadata.len())),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+        let Some(path) = join_contained(self, path) else {
+            return Ok(None);
+        };
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(AssetMetadata {
+                size: metadata.len(),
+                compressed_size: None,
+                modified: metadata.modified().ok(),
+            })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        // only walk the deepest directory that can contain matches
+        let dir = match prefix.rfind('/') {
+            Some(pos) => &prefix[..pos],
+            None => "",
+        };
+        let Some(root) = join_contained(self, dir) else {
+            return Ok(Vec::new());
+        };
+        let mut paths = Vec::new();
+        match walk_dir(&root, dir, &mut paths) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        paths.retain(|path| path.starts_with(prefix));
+        Ok(paths)
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+/// The CRC32 checksum of the data, as stored in vpk and zip files
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Read a range of bytes from the current position of a reader, skipping everything before the range
+pub(crate) fn read_range<R: Read + ?Sized>(
+    reader: &mut R,
+    range: Range<u64>,
+) -> std::io::Result<Vec<u8>> {
+    copy(&mut reader.take(range.start), &mut sink())?;
+    let mut data = Vec::new();
+    reader
+        .take(range.end.saturating_sub(range.start))
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Collect the paths of all files below `dir`, relative to the source root.
+///
+/// Entries that can't be read below `dir` are skipped with a warning.
+fn walk_dir(dir: &Path, relative: &str, paths: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in dir.read_dir()? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        let path = if relative.is_empty() {
+            name
+        } else {
+            format!("{relative}/{name}")
+        };
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if let Err(error) = walk_dir(&entry.path(), &path, paths) {
+                warn!(path, %error, "skipping unreadable directory");
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Source that combines a primary source with a fallback for assets missing from the primary source.
+///
+/// Unlike adding both sources to the loader separately, the combined source can be added and removed as a single unit.
+#[derive(Debug, Clone)]
+pub struct FallbackSource<P, F> {
+    /// Source that is searched first
+    pub primary: P,
+    /// Source that is searched for the assets missing from the primary source
+    pub fallback: F,
+    log_misses: bool,
+}
+
+impl<P, F> FallbackSource<P, F> {
+    /// Combine a primary source with a fallback, see [`log_misses`](Self::log_misses) to log the fallbacks
+    pub fn new(primary: P, fallback: F) -> Self {
+        FallbackSource {
+            primary,
+            fallback,
+            log_misses: false,
+        }
+    }
+
+    /// Log a debug message for every asset that is missing from the primary source
+    pub fn log_misses(mut self, log_misses: bool) -> Self {
+        self.log_misses = log_misses;
+        self
+    }
+
+    fn miss(&self, path: &str) {
+        if self.log_misses {
+            debug!(path, "asset not found in primary source, trying fallback");
+        }
+    }
+}
+
+impl<P: AssetSource, F: AssetSource> AssetSource for FallbackSource<P, F> {
+    fn has(&self, path: &str) -> Result<bool, LoaderError> {
+        if self.primary.has(path)? {
+            return Ok(true);
+        }
+        self.miss(path);
+        self.fallback.has(path)
+    }
+
+    fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        if let Some(data) = self.primary.load(path)? {
+            return Ok(Some(data));
+        }
+        self.miss(path);
+        self.fallback.load(path)
+    }
+
+    fn load_shared(&self, path: &str) -> Result<Option<Arc<[u8]>>, LoaderError> {
+        if let Some(data) = self.primary.load_shared(path)? {
+            return Ok(Some(data));
+        }
+        self.miss(path);
+        self.fallback.load_shared(path)
+    }
+
+    fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        if let Some(reader) = self.primary.open(path)? {
+            return Ok(Some(reader));
+        }
+        self.miss(path);
+        self.fallback.open(path)
+    }
+
+    fn load_range(&self, path: &str, range: Range<u64>) -> Result<Option<Vec<u8>>, LoaderError> {
+        if let Some(data) = self.primary.load_range(path, range.clone())? {
+            return Ok(Some(data));
+        }
+        self.miss(path);
+        self.fallback.load_range(path, range)
+    }
+
+    #[cfg(feature = "mmap")]
+    fn load_mmap(&self, path: &str) -> Result<Option<MappedBytes>, LoaderError> {
+        if let Some(data) = self.primary.load_mmap(path)? {
+            return Ok(Some(data));
+        }
+        self.miss(path);
+        self.fallback.load_mmap(path)
+    }
+
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        if let Some(size) = self.primary.size(path)? {
+            return Ok(Some(size));
+        }
+        self.miss(path);
+        self.fallback.size(path)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+        if let Some(metadata) = self.primary.metadata(path)? {
+            return Ok(Some(metadata));
+        }
+        self.miss(path);
+        self.fallback.metadata(path)
+    }
+
+    fn crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        if let Some(crc) = self.primary.crc(path)? {
+            return Ok(Some(crc));
+        }
+        self.miss(path);
+        self.fallback.crc(path)
+    }
+
+    fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+        if self.primary.has(path)? {
+            return self.primary.stored_crc(path);
+        }
+        self.miss(path);
+        self.fallback.stored_crc(path)
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "fallback:{}|{}",
+            self.primary.describe(),
+            self.fallback.describe()
+        )
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        let mut paths: BTreeSet<String> = self.primary.list(prefix)?.into_iter().collect();
+        paths.extend(self.fallback.list(prefix)?);
+        Ok(paths.into_iter().collect())
+    }
+
+    fn is_packed(&self) -> bool {
+        self.primary.is_packed() || self.fallback.is_packed()
+    }
+}
+
+/// Source that serves assets from memory, for generated assets or tests
+///
+/// Paths are cleaned the same way the loader cleans requested paths.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: HashMap<String, Arc<[u8]>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an asset, replacing any existing asset with the same path
+    pub fn insert<P: AsRef<str>, D: Into<Vec<u8>>>(&mut self, path: P, data: D) {
+        self.files
+            .insert(clean_path(path.as_ref()).into_owned(), data.into().into());
+    }
+
+    /// Remove an asset, returning its data if it existed
+    pub fn remove(&mut self, path: &str) -> Option<Vec<u8>> {
+        self.files
+            .remove(clean_path(path).as_ref())
+            .map(|data| data.to_vec())
+    }
+}
+
+impl<P: AsRef<str>, D: Into<Vec<u8>>> FromIterator<(P, D)> for MemorySource {
+    fn from_iter<I: IntoIterator<Item = (P, D)>>(iter: I) -> Self {
+        let mut source = MemorySource::new();
+        for (path, data) in iter {
+            source.insert(path, data);
+        }
+        source
+    }
+}
+
+impl AssetSource for MemorySource {
+    fn has(&self, path: &str) -> Result<bool, LoaderError> {
+        Ok(self.files.contains_key(clean_path(path).as_ref()))
+    }
+
+    fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        Ok(self
+            .files
+            .get(clean_path(path).as_ref())
+            .map(|data| data.to_vec()))
+    }
+
+    fn load_shared(&self, path: &str) -> Result<Option<Arc<[u8]>>, LoaderError> {
+        Ok(self.files.get(clean_path(path).as_ref()).cloned())
+    }
+
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        Ok(self
+            .files
+            .get(clean_path(path).as_ref())
+            .map(|data| data.len() as u64))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        let mut paths: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| path.starts_with(prefix))
+            .cloned()
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn describe(&self) -> String {
+        String::from("memory")
+    }
 }
 
 #[cfg(feature = "vpk")]
 mod vdf {
     use super::AssetSource;
     use crate::LoaderError;
+    #[cfg(feature = "mmap")]
+    use crate::MappedBytes;
+    use std::fs::File;
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+    use std::ops::Range;
     use vpk::VPK;
+    use vpk::entry::VPKEntry;
+
+    /// Read a range of the archived part of an entry, failing if the archive is missing or truncated
+    fn read_archived(
+        path: &str,
+        entry: &VPKEntry,
+        range: Range<u64>,
+        data: &mut Vec<u8>,
+    ) -> Result<(), LoaderError> {
+        let Some(archive) = &entry.archive_path else {
+            return Ok(());
+        };
+        let error = |error| LoaderError::VpkEntry {
+            path: path.into(),
+            archive: archive.to_path_buf(),
+            archive_index: entry.dir_entry.archive_index,
+            error,
+        };
+        let mut file = File::open(archive.as_path()).map_err(error)?;
+        file.seek(SeekFrom::Start(
+            entry.dir_entry.archive_offset as u64 + range.start,
+        ))
+        .map_err(error)?;
+        let length = range.end - range.start;
+        data.reserve_exact(length as usize);
+        let read = file.take(length).read_to_end(data).map_err(error)?;
+        if (read as u64) < length {
+            return Err(error(ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(())
+    }
 
     impl AssetSource for VPK {
         fn has(&self, path: &str) -> Result<bool, LoaderError> {
             Ok(self.tree.contains_key(path))
         }
 
+        /// Entries stored entirely in the preload data are served without touching the archive
         fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
-            if let Some(entry) = self.tree.get(path) {
-                Ok(Some(entry.get()?.into()))
-            } else {
-                Ok(None)
+            let Some(entry) = self.tree.get(path) else {
+                return Ok(None);
+            };
+            let mut data = entry.preload_data.clone();
+            let length = entry.dir_entry.file_length as u64;
+            if length > 0 {
+                read_archived(path, entry, 0..length, &mut data)?;
             }
+            Ok(Some(data))
+        }
+
+        fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+            let Some(entry) = self.tree.get(path) else {
+                return Ok(None);
+            };
+            let preload = Cursor::new(entry.preload_data.clone());
+            let Some(archive) = entry
+                .archive_path
+                .as_ref()
+                .filter(|_| entry.dir_entry.file_length > 0)
+            else {
+                return Ok(Some(Box::new(preload)));
+            };
+            let error = |error| LoaderError::VpkEntry {
+                path: path.into(),
+                archive: archive.to_path_buf(),
+                archive_index: entry.dir_entry.archive_index,
+                error,
+            };
+            let mut file = File::open(archive.as_path()).map_err(error)?;
+            file.seek(SeekFrom::Start(entry.dir_entry.archive_offset as u64))
+                .map_err(error)?;
+            let file = file.take(entry.dir_entry.file_length as u64);
+            Ok(Some(Box::new(preload.chain(file))))
+        }
+
+        /// Only the requested part of the entry is read from the archive
+        fn load_range(
+            &self,
+            path: &str,
+            range: Range<u64>,
+        ) -> Result<Option<Vec<u8>>, LoaderError> {
+            let Some(entry) = self.tree.get(path) else {
+                return Ok(None);
+            };
+            let preload = entry.preload_data.as_slice();
+            let preload_len = preload.len() as u64;
+            let mut data = preload
+                .get(range.start.min(preload_len) as usize..range.end.min(preload_len) as usize)
+                .unwrap_or_default()
+                .to_vec();
+
+            let archived = range.start.saturating_sub(preload_len)
+                ..range
+                    .end
+                    .saturating_sub(preload_len)
+                    .min(entry.dir_entry.file_length as u64);
+            if !archived.is_empty() {
+                read_archived(path, entry, archived, &mut data)?;
+            }
+            Ok(Some(data))
+        }
+
+        fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+            Ok(self
+                .tree
+                .get(path)
+                .map(|entry| entry.preload_data.len() as u64 + entry.dir_entry.file_length as u64))
+        }
+
+        fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+            Ok(self.tree.get(path).map(|entry| entry.dir_entry.crc32))
+        }
+
+        /// Entries without preload data are mapped from their archive, other entries are loaded into memory
+        #[cfg(feature = "mmap")]
+        fn load_mmap(&self, path: &str) -> Result<Option<MappedBytes>, LoaderError> {
+            let Some(entry) = self.tree.get(path) else {
+                return Ok(None);
+            };
+            let archive = match &entry.archive_path {
+                Some(archive) if entry.preload_data.is_empty() => archive,
+                _ => return Ok(self.load(path)?.map(MappedBytes::from)),
+            };
+            let error = |error| LoaderError::VpkEntry {
+                path: path.into(),
+                archive: archive.to_path_buf(),
+                archive_index: entry.dir_entry.archive_index,
+                error,
+            };
+            let file = File::open(archive.as_path()).map_err(error)?;
+            let offset = entry.dir_entry.archive_offset as u64;
+            let length = entry.dir_entry.file_length as u64;
+            // accessing a mapping past the end of the file crashes instead of failing
+            if file.metadata().map_err(error)?.len() < offset + length {
+                return Err(error(ErrorKind::UnexpectedEof.into()));
+            }
+            Ok(Some(MappedBytes::map_range(
+                &file,
+                offset,
+                length as usize,
+            )?))
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+            Ok(self
+                .tree
+                .keys()
+                .filter(|path| path.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+
+        fn describe(&self) -> String {
+            let name = self.root_path.file_name().unwrap_or_default();
+            format!("vpk:{}", name.to_string_lossy())
+        }
+
+        fn is_packed(&self) -> bool {
+            true
+        }
+
+        fn as_vpk(&self) -> Option<&VPK> {
+            Some(self)
         }
     }
 }
@@ -61,17 +728,114 @@ mod vbsp {
         fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
             Ok(self.get(path)?)
         }
+
+        /// Note that `Packfile` doesn't expose its file names, so listing has to copy the packfile.
+        ///
+        /// Prefer mounting the archive from [`Packfile::into_zip`] when listing is needed.
+        fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+            self.clone().into_zip().list(prefix)
+        }
+
+        fn describe(&self) -> String {
+            String::from("packfile")
+        }
+
+        fn is_packed(&self) -> bool {
+            true
+        }
     }
 }
 
 #[cfg(feature = "zip")]
-mod zip {
-    use super::AssetSource;
+pub(crate) mod zip {
+    use super::{AssetMetadata, AssetSource, read_range};
     use crate::LoaderError;
-    use std::io::{Read, Seek};
+    use std::fs::File;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::ops::Range;
+    use std::path::{Path, PathBuf};
     use std::sync::Mutex;
     use zip::result::ZipError;
-    use zip::ZipArchive;
+    use zip::{CompressionMethod, ZipArchive};
+
+    /// A zip file on disk, uncompressed files are streamed from the zip file when opened
+    pub(crate) struct ZipFileSource {
+        zip: Mutex<ZipArchive<File>>,
+        path: PathBuf,
+    }
+
+    impl ZipFileSource {
+        pub fn open(path: &Path) -> Result<Self, LoaderError> {
+            Ok(ZipFileSource {
+                zip: Mutex::new(ZipArchive::new(File::open(path)?)?),
+                path: path.into(),
+            })
+        }
+    }
+
+    impl AssetSource for ZipFileSource {
+        fn has(&self, path: &str) -> Result<bool, LoaderError> {
+            self.zip.has(path)
+        }
+
+        fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+            self.zip.load(path)
+        }
+
+        /// Compressed files are decompressed into memory
+        fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+            let (start, size) = {
+                let mut zip = self.zip.lock().unwrap();
+                let entry = match zip.by_name(path) {
+                    Ok(entry) => entry,
+                    Err(ZipError::FileNotFound) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+                if entry.compression() != CompressionMethod::Stored {
+                    drop(entry);
+                    drop(zip);
+                    return self.zip.open(path);
+                }
+                (entry.data_start(), entry.size())
+            };
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(start))?;
+            Ok(Some(Box::new(file.take(size))))
+        }
+
+        fn load_range(
+            &self,
+            path: &str,
+            range: Range<u64>,
+        ) -> Result<Option<Vec<u8>>, LoaderError> {
+            self.zip.load_range(path, range)
+        }
+
+        fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+            self.zip.size(path)
+        }
+
+        fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+            self.zip.metadata(path)
+        }
+
+        fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+            self.zip.stored_crc(path)
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+            self.zip.list(prefix)
+        }
+
+        fn describe(&self) -> String {
+            let name = self.path.file_name().unwrap_or_default();
+            format!("zip:{}", name.to_string_lossy())
+        }
+
+        fn is_packed(&self) -> bool {
+            true
+        }
+    }
 
     impl<Reader: Read + Seek> AssetSource for Mutex<ZipArchive<Reader>> {
         fn has(&self, path: &str) -> Result<bool, LoaderError> {
@@ -86,16 +850,221 @@ mod zip {
             let mut zip = self.lock().unwrap();
             let mut entry = match zip.by_name(path) {
                 Ok(entry) => entry,
-                Err(ZipError::FileNotFound) => {
-                    return Ok(None);
-                }
-                Err(e) => {
-                    return Err(e.into());
-                }
+                Err(ZipError::FileNotFound) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            // the size is only used as a hint, in case it doesn't match the actual data
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data)?;
+            Ok(Some(data))
+        }
+
+        fn load_range(
+            &self,
+            path: &str,
+            range: Range<u64>,
+        ) -> Result<Option<Vec<u8>>, LoaderError> {
+            let mut zip = self.lock().unwrap();
+            let mut entry = match zip.by_name(path) {
+                Ok(entry) => entry,
+                Err(ZipError::FileNotFound) => return Ok(None),
+                Err(e) => return Err(e.into()),
             };
-            let mut buff = vec![0; entry.size() as usize];
-            entry.read_exact(&mut buff)?;
-            Ok(Some(buff))
+            Ok(Some(read_range(&mut entry, range)?))
+        }
+
+        fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+            match self.lock().unwrap().by_name(path) {
+                Ok(entry) => Ok(Some(entry.size())),
+                Err(ZipError::FileNotFound) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+            match self.lock().unwrap().by_name(path) {
+                Ok(entry) => Ok(Some(AssetMetadata {
+                    size: entry.size(),
+                    compressed_size: (entry.compression() != CompressionMethod::Stored)
+                        .then_some(entry.compressed_size()),
+                    modified: None,
+                })),
+                Err(ZipError::FileNotFound) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        fn stored_crc(&self, path: &str) -> Result<Option<u32>, LoaderError> {
+            match self.lock().unwrap().by_name(path) {
+                Ok(entry) => Ok(Some(entry.crc32())),
+                Err(ZipError::FileNotFound) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+            Ok(self
+                .lock()
+                .unwrap()
+                .file_names()
+                .filter(|path| !path.ends_with('/') && path.starts_with(prefix))
+                .map(String::from)
+                .collect())
+        }
+
+        fn describe(&self) -> String {
+            String::from("zip")
+        }
+
+        fn is_packed(&self) -> bool {
+            true
         }
     }
 }
+
+#[test]
+fn test_crc32() {
+    assert_eq!(0, crc32(b""));
+    assert_eq!(0xcbf43926, crc32(b"123456789"));
+}
+
+#[test]
+fn test_dir_containment() {
+    use crate::Loader;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("root");
+    create_dir_all(root.join("sub")).unwrap();
+    write(root.join("file.txt"), "inside").unwrap();
+    write(dir.path().join("secret.txt"), "outside").unwrap();
+
+    assert!(root.has("sub/../file.txt").unwrap());
+    for path in [
+        "../secret.txt",
+        "sub/../../secret.txt",
+        dir.path().join("secret.txt").to_str().unwrap(),
+    ] {
+        assert!(!root.has(path).unwrap(), "{path}");
+        assert_eq!(None, root.load(path).unwrap(), "{path}");
+        assert_eq!(None, root.size(path).unwrap(), "{path}");
+        assert!(root.open(path).unwrap().is_none(), "{path}");
+    }
+    assert!(root.list("../").unwrap().is_empty());
+
+    let mut loader = Loader::empty();
+    loader.add_source(root.clone());
+    assert!(loader.exists("sub/../file.txt").unwrap());
+    assert!(!loader.exists("../secret.txt").unwrap());
+    assert!(!loader.exists("a/../../secret.txt").unwrap());
+    assert_eq!(None, loader.load("sub/../../secret.txt").unwrap());
+}
+
+#[test]
+#[cfg(feature = "zip")]
+fn test_zip_source() {
+    use ::zip::write::{FileOptions, ZipWriter};
+    use ::zip::{CompressionMethod, ZipArchive};
+    use std::io::{Cursor, Write};
+    use std::sync::Mutex;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let files: [(&str, &[u8], CompressionMethod); 3] = [
+        ("empty.txt", b"", CompressionMethod::Stored),
+        ("deflated.txt", &[b'a'; 1024], CompressionMethod::Deflated),
+        ("stored.txt", b"stored", CompressionMethod::Stored),
+    ];
+    for (name, data, compression) in files {
+        zip.start_file(name, FileOptions::default().compression_method(compression))
+            .unwrap();
+        zip.write_all(data).unwrap();
+    }
+    let zip = Mutex::new(ZipArchive::new(zip.finish().unwrap()).unwrap());
+
+    for (name, data, _) in files {
+        assert!(zip.has(name).unwrap());
+        assert_eq!(Some(data.to_vec()), zip.load(name).unwrap());
+        assert_eq!(Some(crc32(data)), zip.crc(name).unwrap());
+    }
+    let deflated = zip.metadata("deflated.txt").unwrap().unwrap();
+    assert_eq!(1024, deflated.size);
+    assert!(deflated.compressed_size.unwrap() < 1024);
+    assert_eq!(
+        None,
+        zip.metadata("stored.txt").unwrap().unwrap().compressed_size
+    );
+    assert!(!zip.has("missing.txt").unwrap());
+    assert_eq!(None, zip.load("missing.txt").unwrap());
+}
+
+#[test]
+fn test_fallback_source() {
+    use std::fs::write;
+
+    let primary = tempfile::tempdir().unwrap();
+    let fallback = tempfile::tempdir().unwrap();
+    write(primary.path().join("both.txt"), "primary").unwrap();
+    write(fallback.path().join("both.txt"), "fallback").unwrap();
+    write(fallback.path().join("fallback.txt"), "fallback").unwrap();
+
+    let source = FallbackSource::new(primary.path().to_path_buf(), fallback.path().to_path_buf())
+        .log_misses(true);
+    assert_eq!(Some(b"primary".to_vec()), source.load("both.txt").unwrap());
+    assert_eq!(
+        Some(b"fallback".to_vec()),
+        source.load("fallback.txt").unwrap()
+    );
+    assert!(!source.has("missing.txt").unwrap());
+    assert_eq!(vec!["both.txt", "fallback.txt"], source.list("").unwrap());
+
+    let mut data = String::new();
+    let mut reader = source.open("fallback.txt").unwrap().unwrap();
+    reader.read_to_string(&mut data).unwrap();
+    assert_eq!("fallback", data);
+    assert_eq!(
+        Some(b"rim".to_vec()),
+        source.load_range("both.txt", 1..4).unwrap()
+    );
+    assert_eq!(
+        Some(b"all".to_vec()),
+        source.load_range("fallback.txt", 1..4).unwrap()
+    );
+    assert_eq!(
+        Some(crc32(b"fallback")),
+        source.crc("fallback.txt").unwrap()
+    );
+    assert_eq!(None, source.crc("missing.txt").unwrap());
+    assert!(!source.is_packed());
+}
+
+#[test]
+fn test_memory_source() {
+    use crate::Loader;
+
+    let mut source = MemorySource::from_iter([
+        ("materials/generated.vmt", "\"UnlitGeneric\" {}"),
+        ("materials/foo/../bar.vmt", "bar"),
+    ]);
+    source.insert("@materials/cached.vmt", "cached");
+    assert!(source.has("materials/bar.vmt").unwrap());
+    assert!(source.has("materials/baz/../bar.vmt").unwrap());
+    assert_eq!(
+        Some(b"cached".to_vec()),
+        source.load("materials/cached.vmt").unwrap()
+    );
+    assert_eq!(
+        vec![
+            "materials/bar.vmt",
+            "materials/cached.vmt",
+            "materials/generated.vmt"
+        ],
+        source.list("materials/").unwrap()
+    );
+    assert_eq!(Some(6), source.size("materials/cached.vmt").unwrap());
+    assert_eq!(Some(b"bar".to_vec()), source.remove("materials/bar.vmt"));
+    assert!(!source.has("materials/bar.vmt").unwrap());
+
+    let mut loader = Loader::empty();
+    loader.add_source(source);
+    assert!(loader.exists("@materials/generated.vmt").unwrap());
+}
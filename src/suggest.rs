@@ -0,0 +1,161 @@
+use crate::{Loader, LoaderError, clean_path};
+use std::collections::{BinaryHeap, HashMap};
+
+impl Loader {
+    /// Find the files closest to a path that doesn't exist, to suggest what might have been meant.
+    ///
+    /// Only files with the same extension or in the same directory as `name` are considered, ranked by the edit
+    /// distance of their file name and directory to `name`. Up to `max` paths are returned, closest first.
+    pub fn suggest(&self, name: &str, max: usize) -> Result<Vec<String>, LoaderError> {
+        if max == 0 {
+            return Ok(Vec::new());
+        }
+        let name = clean_path(name).to_ascii_lowercase();
+        let (dir, file_name) = split_path(&name);
+        let extension = file_name.rsplit_once('.').map(|(_, extension)| extension);
+
+        let mut dir_distances: HashMap<String, usize> = HashMap::new();
+        // the closest candidates so far, with the furthest one on top
+        let mut closest: BinaryHeap<(usize, String)> = BinaryHeap::with_capacity(max + 1);
+        for path in self.iter_files() {
+            let path = path?;
+            let lower = path.to_ascii_lowercase();
+            let (candidate_dir, candidate_name) = split_path(&lower);
+            let candidate_extension = candidate_name
+                .rsplit_once('.')
+                .map(|(_, extension)| extension);
+            if candidate_dir != dir && candidate_extension != extension {
+                continue;
+            }
+            // many candidates share the same directory
+            let dir_distance = *dir_distances
+                .entry(candidate_dir.to_string())
+                .or_insert_with(|| {
+                    edit_distance(dir, candidate_dir, usize::MAX).unwrap_or(usize::MAX)
+                });
+            let worst = match closest.peek() {
+                Some((worst, _)) if closest.len() == max => *worst,
+                _ => usize::MAX,
+            };
+            let Some(limit) = worst.checked_sub(dir_distance) else {
+                continue;
+            };
+            let Some(distance) = edit_distance(file_name, candidate_name, limit) else {
+                continue;
+            };
+            closest.push((distance + dir_distance, path));
+            if closest.len() > max {
+                closest.pop();
+            }
+        }
+        Ok(closest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect())
+    }
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+/// Edit distance between two strings, counting the transposition of two adjacent characters as a single edit.
+///
+/// Returns `None` as soon as the distance is known to be larger than `limit`.
+fn edit_distance(a: &str, b: &str, limit: usize) -> Option<usize> {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len().abs_diff(b.len()) > limit {
+        return None;
+    }
+    let mut previous: Vec<usize> = Vec::new();
+    let mut current: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let before_previous = std::mem::replace(&mut previous, current);
+        current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        // a row only builds on the two rows before it, once both are over the limit every later row is as well
+        let row_min = current.iter().min().copied().unwrap_or_default();
+        let previous_min = previous.iter().min().copied().unwrap_or_default();
+        if row_min > limit && previous_min > limit {
+            return None;
+        }
+    }
+    Some(current[b.len()]).filter(|distance| *distance <= limit)
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(Some(0), edit_distance("foo", "foo", usize::MAX));
+    assert_eq!(Some(3), edit_distance("", "foo", usize::MAX));
+    assert_eq!(Some(1), edit_distance("foo.vtm", "foo.vmt", usize::MAX));
+    assert_eq!(Some(1), edit_distance("scout", "scuot", usize::MAX));
+    assert_eq!(Some(3), edit_distance("kitten", "sitting", usize::MAX));
+    assert_eq!(Some(3), edit_distance("kitten", "sitting", 3));
+    assert_eq!(None, edit_distance("kitten", "sitting", 2));
+    assert_eq!(None, edit_distance("", "foo", 2));
+    assert_eq!(None, edit_distance("scattergun", "soldier", 4));
+}
+
+#[test]
+fn test_suggest() {
+    use crate::source::MemorySource;
+
+    let mut loader = Loader::empty();
+    loader.add_source(MemorySource::from_iter([
+        ("materials/models/player/scout.vmt", ""),
+        ("materials/models/player/soldier.vmt", ""),
+        ("materials/models/weapons/scattergun.vmt", ""),
+        ("materials/models/player/scout.vtf", ""),
+        ("models/player/scout.mdl", ""),
+    ]));
+
+    assert_eq!(
+        vec![
+            "materials/models/player/scout.vmt",
+            "materials/models/player/scout.vtf"
+        ],
+        loader
+            .suggest("materials/models/player/scuot.vmt", 2)
+            .unwrap()
+    );
+    // files in other directories with the same extension are considered
+    assert_eq!(
+        vec!["materials/models/player/scout.vmt"],
+        loader
+            .suggest("materials/models/players/Scout.vmt", 1)
+            .unwrap()
+    );
+    // the mdl doesn't share the directory or extension
+    assert!(
+        !loader
+            .suggest("models/players/scout.txt", 10)
+            .unwrap()
+            .contains(&"models/player/scout.mdl".to_string())
+    );
+    assert!(
+        loader
+            .suggest("materials/models/player/scout.vmt", 0)
+            .unwrap()
+            .is_empty()
+    );
+    // ties are ordered by path, like without a limit
+    assert_eq!(
+        vec![
+            "materials/models/player/scout.vmt",
+            "materials/models/player/scout.vtf",
+            "materials/models/player/soldier.vmt",
+        ],
+        loader
+            .suggest("materials/models/player/scout.vmt", 3)
+            .unwrap()
+    );
+}
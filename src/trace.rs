@@ -0,0 +1,83 @@
+use crate::{Found, Loader, LoaderError};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::warn;
+
+impl Loader {
+    /// Start recording every successful load to the file at `path`, replacing any existing file.
+    ///
+    /// Each load is written as a line containing the resolved path and the index of the source it was loaded from,
+    /// separated by a tab. Loads served from the cache are recorded as well.
+    /// The trace is shared between clones of the loader, starting a new trace stops the previous one.
+    pub fn start_trace<P: AsRef<Path>>(&self, path: P) -> Result<(), LoaderError> {
+        let file = BufWriter::new(File::create(path)?);
+        if let Some(mut previous) = self.trace.lock().unwrap().replace(file) {
+            previous.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Stop recording loads, flushing the trace file.
+    pub fn stop_trace(&self) -> Result<(), LoaderError> {
+        if let Some(mut trace) = self.trace.lock().unwrap().take() {
+            trace.flush()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_tracing(&self) -> bool {
+        self.trace.lock().unwrap().is_some()
+    }
+
+    pub(crate) fn record_trace<T>(&self, found: &Found<T>) {
+        let mut trace = self.trace.lock().unwrap();
+        let Some(file) = trace.as_mut() else {
+            return;
+        };
+        if let Err(error) = writeln!(file, "{}\t{}", found.path, found.source) {
+            warn!(%error, "failed to write load trace");
+        }
+    }
+}
+
+#[test]
+fn test_trace() {
+    use std::fs::{read_to_string, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let data = tempfile::tempdir().unwrap();
+    write(data.path().join("a.txt"), "a").unwrap();
+    write(data.path().join("b.txt"), "b").unwrap();
+
+    let mut loader = Loader::empty().with_cache(1024);
+    loader.add_source(dir.path().to_path_buf());
+    loader.add_source(data.path().to_path_buf());
+    loader.load("a.txt").unwrap();
+
+    let trace = dir.path().join("trace.txt");
+    loader.start_trace(&trace).unwrap();
+    loader.load("a.txt").unwrap();
+    loader.load("B.txt").unwrap();
+    loader.load("missing.txt").unwrap();
+    // reads of cached files are recorded like loads
+    loader.open("a.txt").unwrap().unwrap();
+    loader.load_range("a.txt", 0..1).unwrap().unwrap();
+    loader.load_and_hash("a.txt").unwrap().unwrap();
+    loader.stop_trace().unwrap();
+    loader.load("b.txt").unwrap();
+
+    assert_eq!(
+        "a.txt\t1\nb.txt\t1\na.txt\t1\na.txt\t1\na.txt\t1\n",
+        read_to_string(&trace).unwrap()
+    );
+
+    // as are reads that miss the cache
+    let mut loader = Loader::empty();
+    loader.add_source(data.path().to_path_buf());
+    loader.start_trace(&trace).unwrap();
+    loader.open("a.txt").unwrap().unwrap();
+    loader.load_range("b.txt", 0..1).unwrap().unwrap();
+    loader.stop_trace().unwrap();
+    assert_eq!("a.txt\t0\nb.txt\t0\n", read_to_string(&trace).unwrap());
+}
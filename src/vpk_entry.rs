@@ -0,0 +1,722 @@
+use crate::{Loader, LoaderError, SourceHandle};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{metadata, read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vpk::VPK;
+use vpk::entry::{VPKDirectoryEntry, VPKEntry};
+use vpk::structs::VPKHeader;
+
+/// Archive index used for data stored in the directory file itself
+const DIR_ARCHIVE_INDEX: u16 = 0x7fff;
+
+const VPK_SIGNATURE: u32 = 0x55aa1234;
+
+/// Metadata of a file stored in a vpk archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VpkEntryInfo {
+    /// CRC32 checksum of the file data
+    pub crc32: u32,
+    /// Number of bytes stored in the directory file itself
+    pub preload_length: u16,
+    /// Index of the archive chunk the rest of the data is stored in, `0x7fff` if stored in the directory file
+    pub archive_index: u16,
+    /// Offset of the data in the archive chunk
+    pub archive_offset: u32,
+    /// Length of the data in the archive chunk, excluding the preload data
+    pub file_length: u32,
+    /// Path of the archive chunk the data is read from, if any
+    pub archive_path: Option<PathBuf>,
+}
+
+/// Sizes of the content of the mounted vpk files.
+///
+/// Vpk files don't compress their content, the difference between the packed and unpacked sizes is the
+/// overhead of the directory tree and any unused space in the archives.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VpkSizeReport {
+    /// Total size of the vpk files on disk, including the directory files and all archive chunks
+    pub packed: u64,
+    /// Total size of all files stored in the vpk files
+    pub unpacked: u64,
+    /// Number of files stored in the vpk files
+    pub files: u64,
+    /// Sizes of the stored files by lowercase extension, files without extension are listed under an empty string
+    pub extensions: BTreeMap<String, VpkExtensionSize>,
+}
+
+/// Sizes of the files with one extension in a [`VpkSizeReport`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VpkExtensionSize {
+    pub files: u64,
+    pub unpacked: u64,
+}
+
+impl VpkSizeReport {
+    /// Ratio of the unpacked to the packed size, `0` if no vpk files are mounted
+    pub fn ratio(&self) -> f64 {
+        if self.packed == 0 {
+            0.0
+        } else {
+            self.unpacked as f64 / self.packed as f64
+        }
+    }
+}
+
+impl Loader {
+    /// Add the vpk at the specified path as a source, searched after all existing sources.
+    ///
+    /// For split vpk files the path should point to the directory file, like `tf2_misc_dir.vpk`.
+    pub fn mount_vpk<P: AsRef<Path>>(&mut self, path: P) -> Result<SourceHandle, LoaderError> {
+        let vpk = open_vpk(path.as_ref())?;
+        Ok(self.add_source(vpk))
+    }
+
+    /// Get the vpk metadata for a file, from the first vpk source that contains it.
+    ///
+    /// Returns `None` if the path isn't found in any vpk, even if other sources contain it.
+    pub fn vpk_entry_info(&self, name: &str) -> Option<VpkEntryInfo> {
+        let found = self.find(name, |source, path| {
+            let Some(entry) = source.as_vpk().and_then(|vpk| vpk.tree.get(path)) else {
+                return Ok::<_, LoaderError>(None);
+            };
+            let dir_entry = &entry.dir_entry;
+            Ok(Some(VpkEntryInfo {
+                crc32: dir_entry.crc32,
+                preload_length: dir_entry.preload_length,
+                archive_index: dir_entry.archive_index,
+                archive_offset: dir_entry.archive_offset,
+                file_length: dir_entry.file_length,
+                archive_path: entry.archive_path.as_deref().cloned(),
+            }))
+        });
+        found.ok().flatten().map(|found| found.value)
+    }
+
+    /// Sum up the sizes of the content of all mounted vpk files.
+    ///
+    /// Files present in multiple vpk files are counted for every vpk they are stored in.
+    pub fn vpk_size_report(&self) -> VpkSizeReport {
+        let mut report = VpkSizeReport::default();
+        let mut archives = BTreeSet::new();
+        self.for_each_source(|_, source| {
+            let Some(vpk) = source.as_vpk() else {
+                return;
+            };
+            archives.insert(vpk.root_path.as_ref().clone());
+            for (path, entry) in vpk.tree.iter() {
+                let size =
+                    entry.dir_entry.preload_length as u64 + entry.dir_entry.file_length as u64;
+                let file_name = path.rsplit('/').next().unwrap_or(path);
+                let extension = file_name
+                    .rsplit_once('.')
+                    .map(|(_, extension)| extension.to_ascii_lowercase())
+                    .unwrap_or_default();
+                let extension = report.extensions.entry(extension).or_default();
+                extension.files += 1;
+                extension.unpacked += size;
+                report.files += 1;
+                report.unpacked += size;
+                if let Some(archive) = &entry.archive_path {
+                    archives.insert(archive.as_ref().clone());
+                }
+            }
+        });
+        report.packed = archives
+            .iter()
+            .filter_map(|archive| metadata(archive).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        report
+    }
+}
+
+/// Open a vpk directory file, resolving the archive chunks next to it.
+///
+/// The chunks of `foo_dir.vpk` are `foo_000.vpk`, `foo_001.vpk`, etc. in the same directory as the directory file,
+/// the vpk crate derives the chunk names by replacing every `dir` in the file name which breaks for names like
+/// `redirect_dir.vpk`.
+///
+/// Old vpk files without a header, from before the format was versioned, are supported as well.
+pub(crate) fn open_vpk(path: &Path) -> Result<VPK, LoaderError> {
+    let mut vpk = match vpk::from_path(path) {
+        Ok(vpk) => vpk,
+        Err(vpk::Error::InvalidSignature) => read_unversioned(path)?,
+        Err(e) => return Err(vpk_error(e)),
+    };
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let base = name.strip_suffix("dir.vpk").unwrap_or(name);
+    let mut chunks: HashMap<u16, Arc<PathBuf>> = HashMap::new();
+    chunks.insert(DIR_ARCHIVE_INDEX, vpk.root_path.clone());
+    for entry in vpk.tree.values_mut() {
+        if entry.archive_path.is_some() {
+            let index = entry.dir_entry.archive_index;
+            let chunk = chunks
+                .entry(index)
+                .or_insert_with(|| Arc::new(dir.join(format!("{base}{index:03}.vpk"))));
+            entry.archive_path = Some(chunk.clone());
+        }
+    }
+    Ok(vpk)
+}
+
+/// Read a vpk that has no header and starts directly with the directory tree
+fn read_unversioned(path: &Path) -> Result<VPK, LoaderError> {
+    let invalid = || LoaderError::Other(format!("{} is not a vpk file", path.display()));
+    let data = read(path)?;
+    if data.get(..4) == Some(VPK_SIGNATURE.to_le_bytes().as_slice()) {
+        return Err(invalid());
+    }
+
+    let mut reader = TreeReader {
+        data: &data,
+        pos: 0,
+    };
+    let mut entries = Vec::new();
+    loop {
+        let ext = reader.string().ok_or_else(invalid)?;
+        if ext.is_empty() {
+            break;
+        }
+        loop {
+            let dir = reader.string().ok_or_else(invalid)?;
+            if dir.is_empty() {
+                break;
+            }
+            loop {
+                let name = reader.string().ok_or_else(invalid)?;
+                if name.is_empty() {
+                    break;
+                }
+                let dir_entry = VPKDirectoryEntry {
+                    crc32: reader.u32().ok_or_else(invalid)?,
+                    preload_length: reader.u16().ok_or_else(invalid)?,
+                    archive_index: reader.u16().ok_or_else(invalid)?,
+                    archive_offset: reader.u32().ok_or_else(invalid)?,
+                    file_length: reader.u32().ok_or_else(invalid)?,
+                    suffix: reader.u16().ok_or_else(invalid)?,
+                };
+                if dir_entry.suffix != 0xffff {
+                    return Err(invalid());
+                }
+                let preload_data = reader
+                    .bytes(dir_entry.preload_length as usize)
+                    .ok_or_else(invalid)?
+                    .to_vec();
+                let path = match dir {
+                    " " => format!("{name}.{ext}"),
+                    dir => format!("{dir}/{name}.{ext}"),
+                };
+                entries.push((path, dir_entry, preload_data));
+            }
+        }
+    }
+
+    let tree_length = reader.pos as u32;
+    let root_path = Arc::new(path.to_path_buf());
+    let mut vpk = VPK {
+        header_length: 0,
+        header: VPKHeader {
+            signature: 0,
+            version: 0,
+            tree_length,
+        },
+        header_v2: None,
+        header_v2_checksum: None,
+        tree: Default::default(),
+        root_path: root_path.clone(),
+    };
+    for (path, mut dir_entry, preload_data) in entries {
+        if dir_entry.archive_index == DIR_ARCHIVE_INDEX {
+            dir_entry.archive_offset += tree_length;
+        }
+        // chunk paths are resolved by the caller
+        let archive_path = (dir_entry.file_length != 0).then(|| root_path.clone());
+        let entry = VPKEntry {
+            dir_entry,
+            archive_path,
+            preload_data,
+        };
+        vpk.tree.insert(path, entry);
+    }
+    Ok(vpk)
+}
+
+struct TreeReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TreeReader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let len = self.data.get(self.pos..)?.iter().position(|c| *c == 0)?;
+        let string = std::str::from_utf8(self.bytes(len)?).ok()?;
+        self.pos += 1;
+        Some(string)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+}
+
+fn vpk_error(error: vpk::Error) -> LoaderError {
+    match error {
+        vpk::Error::ReadError(error) => LoaderError::Io(error),
+        error => LoaderError::Other(error.to_string()),
+    }
+}
+
+/// Write a vpk with all file data stored in the first archive chunk, version 0 writes a vpk without header
+#[cfg(test)]
+pub(crate) fn build_test_vpk(dir: &Path, base: &str, version: u32, files: &[(&str, &[u8])]) {
+    let mut tree = Vec::new();
+    let mut chunk = Vec::new();
+    for (path, data) in files {
+        let (dir, file) = path.rsplit_once('/').unwrap_or((" ", path));
+        let (name, ext) = file.rsplit_once('.').unwrap();
+        for part in [ext, dir, name] {
+            tree.extend_from_slice(part.as_bytes());
+            tree.push(0);
+        }
+        tree.extend_from_slice(&crate::source::crc32(data).to_le_bytes());
+        tree.extend_from_slice(&0u16.to_le_bytes()); // preload length
+        tree.extend_from_slice(&0u16.to_le_bytes()); // archive index
+        tree.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        tree.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        tree.extend_from_slice(&0xffffu16.to_le_bytes());
+        // end of names and paths
+        tree.extend_from_slice(&[0, 0]);
+        chunk.extend_from_slice(data);
+    }
+    // end of extensions
+    tree.push(0);
+
+    let mut vpk = Vec::new();
+    if version > 0 {
+        vpk.extend_from_slice(&VPK_SIGNATURE.to_le_bytes());
+        vpk.extend_from_slice(&version.to_le_bytes());
+        vpk.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    }
+    vpk.extend_from_slice(&tree);
+    std::fs::write(dir.join(format!("{base}_dir.vpk")), vpk).unwrap();
+    std::fs::write(dir.join(format!("{base}_000.vpk")), chunk).unwrap();
+}
+
+/// Write a standalone vpk with all file data stored as preload data
+#[cfg(test)]
+pub(crate) fn build_test_single_vpk(path: &Path, files: &[(&str, &[u8])]) {
+    let mut tree = Vec::new();
+    for (path, data) in files {
+        let (dir, file) = path.rsplit_once('/').unwrap_or((" ", path));
+        let (name, ext) = file.rsplit_once('.').unwrap();
+        for part in [ext, dir, name] {
+            tree.extend_from_slice(part.as_bytes());
+            tree.push(0);
+        }
+        tree.extend_from_slice(&crate::source::crc32(data).to_le_bytes());
+        tree.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        tree.extend_from_slice(&DIR_ARCHIVE_INDEX.to_le_bytes());
+        tree.extend_from_slice(&0u32.to_le_bytes());
+        tree.extend_from_slice(&0u32.to_le_bytes());
+        tree.extend_from_slice(&0xffffu16.to_le_bytes());
+        tree.extend_from_slice(data);
+        // end of names and paths
+        tree.extend_from_slice(&[0, 0]);
+    }
+    // end of extensions
+    tree.push(0);
+
+    let mut vpk = Vec::new();
+    vpk.extend_from_slice(&VPK_SIGNATURE.to_le_bytes());
+    vpk.extend_from_slice(&1u32.to_le_bytes());
+    vpk.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    vpk.extend_from_slice(&tree);
+    std::fs::write(path, vpk).unwrap();
+}
+
+#[test]
+fn test_broken_chunk() {
+    use crate::AssetSource;
+
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(
+        dir.path(),
+        "pak01",
+        1,
+        &[("scripts/a.txt", b"first"), ("scripts/b.txt", b"second")],
+    );
+    let vpk = vpk::from_path(dir.path().join("pak01_dir.vpk")).unwrap();
+
+    // truncate the chunk in the middle of the second file
+    std::fs::write(dir.path().join("pak01_000.vpk"), "firstsec").unwrap();
+    assert_eq!(Some(b"first".to_vec()), vpk.load("scripts/a.txt").unwrap());
+    let error = vpk.load("scripts/b.txt").unwrap_err();
+    assert!(matches!(
+        &error,
+        LoaderError::VpkEntry { path, archive_index: 0, archive, .. }
+            if path == "scripts/b.txt" && archive.ends_with("pak01_000.vpk")
+    ));
+    assert!(vpk.load_range("scripts/b.txt", 0..3).is_ok());
+    assert!(vpk.load_range("scripts/b.txt", 2..6).is_err());
+
+    std::fs::remove_file(dir.path().join("pak01_000.vpk")).unwrap();
+    let error = vpk.load("scripts/a.txt").unwrap_err();
+    assert!(matches!(error, LoaderError::VpkEntry { .. }));
+    assert!(error.to_string().contains("scripts/a.txt"));
+    assert!(vpk.open("scripts/a.txt").is_err());
+}
+
+#[test]
+fn test_mount_vpk() {
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(dir.path(), "pak01", 1, &[("scripts/pak.txt", b"pak")]);
+
+    let mut loader = Loader::empty();
+    assert_eq!(0, loader.source_count());
+    let handle = loader.mount_vpk(dir.path().join("pak01_dir.vpk")).unwrap();
+    assert_eq!(
+        vec!["vpk:pak01_dir.vpk"],
+        loader.sources().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(b"pak".to_vec()),
+        loader.load("scripts/pak.txt").unwrap()
+    );
+    assert!(loader.remove_source(handle));
+    assert!(
+        loader
+            .mount_vpk(dir.path().join("missing_dir.vpk"))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(dir.path(), "pak01", 1, &[("scripts/pak.txt", b"pak")]);
+
+    let mut loader = Loader::empty();
+    loader.mount_vpk(dir.path().join("pak01_dir.vpk")).unwrap();
+    assert_eq!(
+        Some(crate::source::crc32(b"pak")),
+        loader.crc("scripts/pak.txt").unwrap()
+    );
+    assert_eq!(Some(true), loader.verify("scripts/pak.txt").unwrap());
+    assert_eq!(None, loader.verify("scripts/missing.txt").unwrap());
+
+    // directories don't store checksums to verify against
+    let files = tempfile::tempdir().unwrap();
+    std::fs::write(files.path().join("loose.txt"), "loose").unwrap();
+    loader.add_source(files.path().to_path_buf());
+    assert_eq!(
+        Some(crate::source::crc32(b"loose")),
+        loader.crc("loose.txt").unwrap()
+    );
+    assert_eq!(None, loader.stored_crc("loose.txt").unwrap());
+    assert_eq!(None, loader.verify("loose.txt").unwrap());
+
+    std::fs::write(dir.path().join("pak01_000.vpk"), "bad").unwrap();
+    assert_eq!(Some(false), loader.verify("scripts/pak.txt").unwrap());
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn test_vpk_mmap() {
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(
+        dir.path(),
+        "pak01",
+        1,
+        &[("scripts/a.txt", b"first"), ("scripts/b.txt", b"second")],
+    );
+    build_test_single_vpk(
+        &dir.path().join("preload.vpk"),
+        &[("preload.txt", b"preload")],
+    );
+
+    let mut loader = Loader::empty();
+    loader.mount_vpk(dir.path().join("pak01_dir.vpk")).unwrap();
+    loader.mount_vpk(dir.path().join("preload.vpk")).unwrap();
+    let mapped = loader.load_mmap("scripts/b.txt").unwrap().unwrap();
+    assert!(mapped.is_mapped());
+    assert_eq!(b"second", &mapped[..]);
+    let preload = loader.load_mmap("preload.txt").unwrap().unwrap();
+    assert!(!preload.is_mapped());
+    assert_eq!(b"preload", &preload[..]);
+
+    std::fs::write(dir.path().join("pak01_000.vpk"), "first").unwrap();
+    assert!(matches!(
+        loader.load_mmap("scripts/b.txt"),
+        Err(LoaderError::VpkEntry { .. })
+    ));
+}
+
+#[test]
+fn test_preload_only() {
+    use crate::AssetSource;
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("preload.vpk");
+    build_test_single_vpk(&path, &[("scripts/small.txt", b"small")]);
+
+    let vpk = vpk::from_path(&path).unwrap();
+    // the entry is fully preloaded, so the archive doesn't need to be read again
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        Some(b"small".to_vec()),
+        vpk.load("scripts/small.txt").unwrap()
+    );
+    assert_eq!(
+        Some(b"mal".to_vec()),
+        vpk.load_range("scripts/small.txt", 1..4).unwrap()
+    );
+    let mut data = String::new();
+    vpk.open("scripts/small.txt")
+        .unwrap()
+        .unwrap()
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!("small", data);
+}
+
+#[test]
+fn test_standalone_vpk() {
+    use crate::LoaderBuilder;
+    use std::fs::create_dir_all;
+
+    let dir = tempfile::tempdir().unwrap();
+    let tf = dir.path().join("tf");
+    create_dir_all(&tf).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    build_test_vpk(&tf, "pak01", 1, &[("scripts/pak.txt", b"pak")]);
+    build_test_single_vpk(&tf.join("mymod.vpk"), &[("scripts/mod.txt", b"mod")]);
+
+    let loader = LoaderBuilder::new().tf2_dir(dir.path()).build().unwrap();
+    assert_eq!(
+        Some(b"mod".to_vec()),
+        loader.load("scripts/mod.txt").unwrap()
+    );
+    // preload data
+    assert_eq!(
+        Some(b"od".to_vec()),
+        loader.load_range("scripts/mod.txt", 1..3).unwrap()
+    );
+    assert_eq!(
+        Some(b"pak".to_vec()),
+        loader.load("scripts/pak.txt").unwrap()
+    );
+    // the data chunk pak01_000.vpk isn't mounted on its own
+    let mut labels = Vec::new();
+    loader.for_each_source(|_, source| labels.push(source.describe()));
+    labels.retain(|label| label.starts_with("vpk:"));
+    labels.sort();
+    assert_eq!(vec!["vpk:mymod.vpk", "vpk:pak01_dir.vpk"], labels);
+}
+
+#[test]
+fn test_strict_archives() {
+    use crate::{Loader, LoaderError};
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let tf = dir.path().join("tf");
+    create_dir_all(&tf).unwrap();
+    create_dir_all(dir.path().join("hl2")).unwrap();
+    build_test_vpk(&tf, "valid", 1, &[("scripts/valid.txt", b"valid")]);
+    write(tf.join("corrupt_dir.vpk"), b"garbage").unwrap();
+
+    let loader = Loader::with_tf2_dir(dir.path()).unwrap();
+    assert!(loader.exists("scripts/valid.txt").unwrap());
+
+    match Loader::with_tf2_dir_strict(dir.path()) {
+        Err(LoaderError::Archive { path, .. }) => assert_eq!(tf.join("corrupt_dir.vpk"), path),
+        result => panic!("expected an archive error, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_same_named_vpks() {
+    use crate::LoaderBuilder;
+    use std::fs::create_dir_all;
+
+    let dir = tempfile::tempdir().unwrap();
+    let tf = dir.path().join("tf");
+    let hl2 = dir.path().join("hl2");
+    create_dir_all(&tf).unwrap();
+    create_dir_all(&hl2).unwrap();
+    build_test_vpk(
+        &tf,
+        "redirect",
+        1,
+        &[
+            ("scripts/shared.txt", b"tf"),
+            ("scripts/tf.txt", b"tf only"),
+        ],
+    );
+    build_test_vpk(
+        &hl2,
+        "redirect",
+        1,
+        &[
+            ("scripts/shared.txt", b"hl2"),
+            ("scripts/hl2.txt", b"hl2 only"),
+        ],
+    );
+
+    let loader = LoaderBuilder::new().tf2_dir(dir.path()).build().unwrap();
+    assert_eq!(
+        Some(b"tf".to_vec()),
+        loader.load("scripts/shared.txt").unwrap()
+    );
+    assert_eq!(
+        Some(b"tf only".to_vec()),
+        loader.load("scripts/tf.txt").unwrap()
+    );
+    assert_eq!(
+        Some(b"hl2 only".to_vec()),
+        loader.load("scripts/hl2.txt").unwrap()
+    );
+
+    let mut data = String::new();
+    let mut reader = loader.open("scripts/hl2.txt").unwrap().unwrap();
+    std::io::Read::read_to_string(&mut reader, &mut data).unwrap();
+    assert_eq!("hl2 only", data);
+    assert_eq!(Some(8), loader.size("scripts/hl2.txt").unwrap());
+    assert_eq!(
+        Some(b"only".to_vec()),
+        loader.load_range("scripts/hl2.txt", 4..100).unwrap()
+    );
+
+    let info = loader.vpk_entry_info("scripts/hl2.txt").unwrap();
+    assert_eq!(Some(hl2.join("redirect_000.vpk")), info.archive_path);
+    assert_eq!(8, info.file_length);
+}
+
+#[test]
+fn test_unversioned_vpk() {
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(
+        dir.path(),
+        "legacy",
+        0,
+        &[("materials/legacy.vmt", b"legacy"), ("root.txt", b"root")],
+    );
+
+    let vpk = open_vpk(&dir.path().join("legacy_dir.vpk")).unwrap();
+    assert_eq!(0, vpk.header.version);
+    let mut loader = Loader::empty();
+    loader.add_source(vpk);
+    assert_eq!(
+        Some(b"legacy".to_vec()),
+        loader.load("materials/legacy.vmt").unwrap()
+    );
+    assert_eq!(Some(b"root".to_vec()), loader.load("root.txt").unwrap());
+
+    std::fs::write(dir.path().join("garbage_dir.vpk"), b"garbage").unwrap();
+    assert!(open_vpk(&dir.path().join("garbage_dir.vpk")).is_err());
+}
+
+#[test]
+fn test_vpk_size_report() {
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(
+        dir.path(),
+        "sizes",
+        1,
+        &[
+            ("materials/a.vmt", b"aaaa"),
+            ("materials/b.VMT", b"bb"),
+            ("models/c.mdl", b"cccccc"),
+        ],
+    );
+
+    let mut loader = Loader::empty();
+    loader.add_source(open_vpk(&dir.path().join("sizes_dir.vpk")).unwrap());
+    let report = loader.vpk_size_report();
+    assert_eq!(3, report.files);
+    assert_eq!(12, report.unpacked);
+    assert_eq!(
+        VpkExtensionSize {
+            files: 2,
+            unpacked: 6,
+        },
+        report.extensions["vmt"]
+    );
+    let dir_size = metadata(dir.path().join("sizes_dir.vpk")).unwrap().len();
+    assert_eq!(dir_size + 12, report.packed);
+    assert!(report.ratio() < 1.0);
+}
+
+#[test]
+fn test_packed_case_insensitive() {
+    use crate::MatchKind;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    build_test_vpk(
+        dir.path(),
+        "case",
+        1,
+        &[
+            ("materials/Models/foo.vmt", b"foo"),
+            ("materials/bar.vmt", b"bar"),
+        ],
+    );
+    let plain = tempfile::tempdir().unwrap();
+    create_dir_all(plain.path().join("MATERIALS")).unwrap();
+    write(plain.path().join("MATERIALS/BAR.VMT"), "plain").unwrap();
+
+    let mut loader = Loader::empty();
+    loader.add_source(open_vpk(&dir.path().join("case_dir.vpk")).unwrap());
+    loader.add_source(plain.path().to_path_buf());
+
+    // mixed case that doesn't match the stored case or the lowercase path
+    assert_eq!(
+        Some(b"foo".to_vec()),
+        loader.load("materials/models/Foo.vmt").unwrap()
+    );
+    assert_eq!(
+        Some(MatchKind::CaseInsensitive),
+        loader.exists_detailed("Materials/MODELS/foo.VMT").unwrap()
+    );
+    assert_eq!(
+        Some(MatchKind::Exact),
+        loader.exists_detailed("materials/Models/foo.vmt").unwrap()
+    );
+    // the vpk is searched first, even though the directory has an exact match
+    assert_eq!(
+        Some(b"bar".to_vec()),
+        loader.load("MATERIALS/BAR.VMT").unwrap()
+    );
+    assert_eq!(
+        vec!["materials/Models/foo.vmt"],
+        loader.list("MATERIALS/models/").unwrap()
+    );
+    assert!(!loader.exists("materials/models/missing.vmt").unwrap());
+    // directories are matched case-insensitively too
+    let mut plain_only = Loader::empty();
+    plain_only.add_source(plain.path().to_path_buf());
+    assert_eq!(
+        Some(b"plain".to_vec()),
+        plain_only.load("materials/Bar.vmt").unwrap()
+    );
+}
@@ -0,0 +1,167 @@
+#[cfg(feature = "mmap")]
+use crate::MappedBytes;
+use crate::{AssetMetadata, AssetSource, LoaderError};
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Directory the workshop maps are loaded from, like the game does
+const MAP_DIR: &str = "maps/workshop/";
+
+/// Source for the maps subscribed to through the steam workshop.
+///
+/// The workshop stores every item in its own directory, `<id>/<name>.bsp`, the game loads these maps as
+/// `maps/workshop/<name>.ugc<id>.bsp`.
+pub(crate) struct WorkshopSource {
+    dir: PathBuf,
+}
+
+impl WorkshopSource {
+    /// Load the workshop content of an app from the steam library containing the tf2 directory
+    pub fn for_tf2_dir(tf2_dir: &Path, app_id: u32) -> Option<Self> {
+        // the tf2 directory is `<library>/steamapps/common/<game>`
+        let dir = tf2_dir
+            .parent()?
+            .parent()?
+            .join("workshop/content")
+            .join(app_id.to_string());
+        dir.is_dir().then_some(WorkshopSource { dir })
+    }
+
+    /// The workshop content directory of the app
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Split a map path into the directory of the workshop item and the file name in the directory
+    fn resolve(&self, path: &str) -> Option<(PathBuf, String)> {
+        let file = path.strip_prefix(MAP_DIR)?.strip_suffix(".bsp")?;
+        let (name, id) = file.rsplit_once(".ugc")?;
+        if name.is_empty() || name.contains('/') || !id.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some((self.dir.join(id), format!("{name}.bsp")))
+    }
+}
+
+impl AssetSource for WorkshopSource {
+    fn has(&self, path: &str) -> Result<bool, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.has(&file),
+            None => Ok(false),
+        }
+    }
+
+    fn load(&self, path: &str) -> Result<Option<Vec<u8>>, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.load(&file),
+            None => Ok(None),
+        }
+    }
+
+    fn open(&self, path: &str) -> Result<Option<Box<dyn Read + Send>>, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.open(&file),
+            None => Ok(None),
+        }
+    }
+
+    fn load_range(&self, path: &str, range: Range<u64>) -> Result<Option<Vec<u8>>, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.load_range(&file, range),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    fn load_mmap(&self, path: &str) -> Result<Option<MappedBytes>, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.load_mmap(&file),
+            None => Ok(None),
+        }
+    }
+
+    fn size(&self, path: &str) -> Result<Option<u64>, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.size(&file),
+            None => Ok(None),
+        }
+    }
+
+    fn metadata(&self, path: &str) -> Result<Option<AssetMetadata>, LoaderError> {
+        match self.resolve(path) {
+            Some((dir, file)) => dir.metadata(&file),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, LoaderError> {
+        let mut paths = Vec::new();
+        for item in self.dir.read_dir()?.filter_map(|item| item.ok()) {
+            let id = item.file_name().to_string_lossy().into_owned();
+            if !item.path().is_dir() || !id.bytes().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+            for file in item.path().read_dir()?.filter_map(|file| file.ok()) {
+                let file = file.file_name().to_string_lossy().into_owned();
+                if let Some(name) = file.strip_suffix(".bsp") {
+                    let path = format!("{MAP_DIR}{name}.ugc{id}.bsp");
+                    if path.starts_with(prefix) {
+                        paths.push(path);
+                    }
+                }
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn describe(&self) -> String {
+        format!("workshop:{}", self.dir.display())
+    }
+}
+
+#[test]
+fn test_workshop_maps() {
+    use crate::LoaderBuilder;
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let tf2_dir = dir.path().join("steamapps/common/Team Fortress 2");
+    let workshop = dir.path().join("steamapps/workshop/content/440");
+    create_dir_all(tf2_dir.join("tf")).unwrap();
+    create_dir_all(tf2_dir.join("hl2")).unwrap();
+    create_dir_all(workshop.join("123")).unwrap();
+    create_dir_all(workshop.join("456")).unwrap();
+    write(workshop.join("123/cp_foo.bsp"), "foo").unwrap();
+    write(workshop.join("456/koth_Bar_rc1.bsp"), "bar").unwrap();
+    write(workshop.join("456/readme.txt"), "").unwrap();
+
+    let loader = LoaderBuilder::new().tf2_dir(&tf2_dir).build().unwrap();
+    assert!(!loader.exists("maps/workshop/cp_foo.ugc123.bsp").unwrap());
+
+    let loader = LoaderBuilder::new()
+        .tf2_dir(&tf2_dir)
+        .mount_workshop(true)
+        .build()
+        .unwrap();
+    assert_eq!(
+        Some(b"foo".to_vec()),
+        loader.load("maps/workshop/cp_foo.ugc123.bsp").unwrap()
+    );
+    assert_eq!(
+        Some(b"bar".to_vec()),
+        loader
+            .load("maps/workshop/koth_bar_rc1.ugc456.bsp")
+            .unwrap()
+    );
+    assert!(!loader.exists("maps/workshop/cp_foo.ugc456.bsp").unwrap());
+    assert!(!loader.exists("maps/workshop/readme.ugc456.txt").unwrap());
+    assert_eq!(
+        vec![
+            "maps/workshop/cp_foo.ugc123.bsp",
+            "maps/workshop/koth_Bar_rc1.ugc456.bsp"
+        ],
+        loader.list("maps/workshop/").unwrap()
+    );
+}